@@ -0,0 +1,104 @@
+// Reading a file vs searching it through a memory map, on a generated log.
+//
+// The log is MINIGREP_BENCH_MB megabytes, 256 unless set, e.g. for something
+// the size of our trace files:
+//
+//     MINIGREP_BENCH_MB=10240 cargo bench
+//
+// It is generated into the temp dir once and reused by later runs.
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use memmap2::Mmap;
+use minigrep::{Match, QueryMatcher, Searcher, Sink};
+
+// only counts, so the benchmark measures the search and not the printing
+struct Count(usize);
+impl Sink for Count {
+    fn matched(&mut self, _result: Match) -> bool {
+        self.0 += 1;
+        true
+    }
+}
+
+fn input() -> PathBuf {
+    let mb: u64 = env::var("MINIGREP_BENCH_MB")
+        .ok()
+        .and_then(|mb| mb.parse().ok())
+        .unwrap_or(256);
+    let path = env::temp_dir().join(format!("minigrep-bench-{}mb.log", mb));
+    if fs::metadata(&path).is_ok_and(|m| m.len() >= mb * 1024 * 1024) {
+        return path;
+    }
+
+    // written next to it and renamed, so a run that is stopped halfway does
+    // not leave a short file behind
+    let partial = path.with_extension("partial");
+    let mut out = BufWriter::new(File::create(&partial).unwrap());
+    // a fixed LCG, so every run searches the same log
+    let mut seed: u64 = 42;
+    let (mut written, mut line) = (0, 0u64);
+    while written < mb * 1024 * 1024 {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+        let level = match seed >> 60 {
+            0 => "ERROR",
+            1..=3 => "WARN",
+            _ => "INFO",
+        };
+        let text = format!(
+            "2026-10-17T12:{:02}:{:02}.{:03}Z {} request {} took {}ms path=/api/v1/items/{}\n",
+            line / 60 % 60,
+            line % 60,
+            seed % 1000,
+            level,
+            line,
+            seed >> 54,
+            seed % 100_000,
+        );
+        out.write_all(text.as_bytes()).unwrap();
+        written += text.len() as u64;
+        line += 1;
+    }
+    out.into_inner().unwrap().sync_all().unwrap();
+    fs::rename(&partial, &path).unwrap();
+    path
+}
+
+fn search(c: &mut Criterion) {
+    let path = input();
+    let len = fs::metadata(&path).unwrap().len();
+
+    // a query that matches now and then, and one that matches every line
+    for query in ["ERROR", "took"] {
+        let matcher = QueryMatcher::Literal(query.to_string());
+        let searcher = Searcher::new(&matcher);
+
+        let mut group = c.benchmark_group(format!("search {}", query));
+        group.throughput(Throughput::Bytes(len));
+        group.sample_size(10);
+        group.bench_function("read", |b| {
+            b.iter(|| {
+                let mut count = Count(0);
+                let reader = BufReader::new(File::open(&path).unwrap());
+                searcher.search_reader(reader, &mut count).unwrap();
+                count.0
+            })
+        });
+        group.bench_function("mmap", |b| {
+            b.iter(|| {
+                let mut count = Count(0);
+                let file = File::open(&path).unwrap();
+                let map = unsafe { Mmap::map(&file) }.unwrap();
+                searcher.search_slice(&map, &mut count);
+                count.0
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, search);
+criterion_main!(benches);
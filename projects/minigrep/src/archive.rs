@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::{decompress, Error};
+
+// Archives whose files are searched one by one, as if they had been unpacked
+// first. They are told apart by their name: plenty of other formats are zip
+// files inside (.jar, .docx), and those are better left alone.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Kind {
+    // also when compressed, e.g. .tar.gz
+    Tar,
+    Zip,
+}
+
+pub fn kind(path: &Path) -> Option<Kind> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    if name.ends_with(".zip") {
+        return Some(Kind::Zip);
+    }
+    [".tar", ".tgz", ".tar.gz", ".tar.zst", ".tar.xz"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+        .then_some(Kind::Tar)
+}
+
+// Hand each file in the archive to `search`, with its path inside the archive.
+// Directories, links and the like are left out. An archive in the archive is
+// not opened in turn, but searched like any other file.
+pub fn for_each_file<F>(path: &Path, kind: Kind, mut search: F) -> Result<(), Error>
+where
+    F: FnMut(&str, Box<dyn BufRead + '_>) -> Result<(), Error>,
+{
+    let read_error = |e| Error::io(path, e);
+    let file = File::open(path).map_err(read_error)?;
+    match kind {
+        Kind::Tar => {
+            // compressed any of the ways a single file can be
+            let reader =
+                decompress::decompressed(Box::new(BufReader::new(file))).map_err(read_error)?;
+            let mut archive = tar::Archive::new(reader);
+            for entry in archive.entries().map_err(read_error)? {
+                let entry = entry.map_err(read_error)?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let name = entry.path().map_err(read_error)?;
+                let name = name.to_string_lossy().into_owned();
+                search(&name, Box::new(BufReader::new(entry)))?;
+            }
+        }
+        Kind::Zip => {
+            let zip_error = |e: zip::result::ZipError| read_error(io::Error::from(e));
+            let mut archive = zip::ZipArchive::new(file).map_err(zip_error)?;
+            for i in 0..archive.len() {
+                let entry = archive.by_index(i).map_err(zip_error)?;
+                if !entry.is_file() {
+                    continue;
+                }
+                let name = entry.name().map_err(zip_error)?.into_owned();
+                search(&name, Box::new(BufReader::new(entry)))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+
+    use crate::testing::{config, TempDir};
+    use crate::{search_file, Printer, QueryMatcher};
+
+    #[test]
+    fn kinds() {
+        assert_eq!(Some(Kind::Zip), kind(Path::new("logs/bundle.ZIP")));
+        assert_eq!(Some(Kind::Tar), kind(Path::new("build.tar.gz")));
+        assert_eq!(Some(Kind::Tar), kind(Path::new("build.tgz")));
+        assert_eq!(None, kind(Path::new("app.log.gz")));
+        assert_eq!(None, kind(Path::new("app.jar")));
+    }
+
+    fn files(path: &Path) -> Vec<(String, String)> {
+        let mut files = Vec::new();
+        for_each_file(path, kind(path).unwrap(), |name, mut reader| {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).unwrap();
+            files.push((name.to_string(), text));
+            Ok(())
+        })
+        .unwrap();
+        files
+    }
+
+    fn write_zip(dir: &Path) -> PathBuf {
+        let zip = dir.join("src.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&zip).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        writer.add_directory("src/", options).unwrap();
+        writer.start_file("src/lib.rs", options).unwrap();
+        writer.write_all(b"fn run() {}\n").unwrap();
+        writer.finish().unwrap();
+        zip
+    }
+
+    #[test]
+    fn tar_and_zip() {
+        let dir = TempDir::new("archive");
+        let expected = vec![(String::from("src/lib.rs"), String::from("fn run() {}\n"))];
+
+        let tar_gz = dir.join("src.tar.gz");
+        let gz = flate2::write::GzEncoder::new(
+            File::create(&tar_gz).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        tar.append_data(&mut header, "src/", io::empty()).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(12);
+        tar.append_data(&mut header, "src/lib.rs", &b"fn run() {}\n"[..])
+            .unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+        assert_eq!(expected, files(&tar_gz));
+
+        let zip = write_zip(&dir);
+        assert_eq!(expected, files(&zip));
+    }
+
+    #[test]
+    fn reported_inside_the_archive() {
+        let dir = TempDir::new("archive-print");
+        let zip = write_zip(&dir);
+
+        let config = config(&["-n", "run"]);
+        let matcher = QueryMatcher::new(&config).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, true, false);
+        search_file(&zip, &matcher, &config, &mut printer).unwrap();
+        assert_eq!(
+            format!("{}!src/lib.rs:1:fn run() {{}}\n", zip.display()),
+            String::from_utf8(printer.into_inner()).unwrap()
+        );
+    }
+}
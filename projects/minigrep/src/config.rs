@@ -0,0 +1,682 @@
+use std::env;
+use std::fs;
+
+use encoding_rs::Encoding;
+
+use crate::{BinaryMode, ColorChoice, Error, FieldFilter, OutputMode};
+
+pub const USAGE: &str = "\
+Usage: minigrep [OPTIONS] <query> [<path>...]
+       minigrep [OPTIONS] -e <query>... [<path>...]
+       minigrep [OPTIONS] --json-field <filter>... [-e <query>...] [<path>...]
+       minigrep index build [OPTIONS] [<dir>...]
+
+Options:
+  -i, --ignore-case         Search case insensitively
+  -s, --case-sensitive      Search case sensitively (overrides CASE_INSENSITIVE)
+  -n, --line-number         Prefix each line with its line number
+  -b, --byte-offset         Prefix each line with its byte offset in the file
+  -w, --word-regexp         Only match whole words
+  -x, --line-regexp         Only match whole lines
+  -v, --invert-match        Print the lines that do not match
+  -c, --count               Print only the number of matching lines per file
+  -l, --files-with-matches  Print only the names of the files with a match
+      --json                Print the results as JSON Lines, one object per line
+  -A, --after-context N     Print N lines after each match
+  -B, --before-context N    Print N lines before each match
+  -C, --context N           Print N lines before and after each match
+  -g, --glob GLOB           Only search files matching GLOB, or not matching !GLOB
+      --iglob GLOB          Like --glob, but ignoring case
+  -t, --type TYPE           Only search files of TYPE, e.g. rust or markdown
+  -T, --type-not TYPE       Do not search files of TYPE
+      --hidden              Also search hidden files and directories
+      --no-ignore           Do not use .gitignore and .ignore files
+  -z, --decompress          Only search gzip, zstd or xz input, skipping other files
+      --encoding NAME       Read the files as NAME, e.g. latin1 (default: UTF-8, or what the BOM says)
+      --binary              Also search binary files, printing only whether they match
+  -a, --text                Search binary files as if they were text
+      --mmap                Search files through memory maps, when they can be
+      --no-mmap             Never search through memory maps (default: only large files)
+      --index               Skip the files that the index says cannot match (not with -c or --json)
+  -F, --follow              Keep searching the file as it grows or is rotated, like tail -F
+  -j, --threads N           Search N files at a time (default: the number of CPUs)
+      --color WHEN          Highlight the matches: auto, always or never (default auto)
+  -e, --regexp QUERY        Search for QUERY, may be given many times
+  -f, --file FILE           Search for each line of FILE, e.g. a list of error codes
+      --json-field FILTER   Only lines that are JSON with a matching field, e.g. level=error
+  -U, --multiline           Let matches span lines, e.g. with \\n in a --regex
+      --regex               Treat the query as a regular expression
+  -r, --replace TEXT        Show each match replaced by TEXT, as a diff
+      --no-preview          With --replace, print the replaced lines instead of a diff
+      --in-place            With --replace, also write the replacements to the files
+  -h, --help                Print this help
+  -V, --version             Print the version
+
+Set CASE_INSENSITIVE in the environment to ignore case by default.
+Use -- to stop reading options, e.g. to search for a query starting with -.
+With more than one query, each line is prefixed with the queries it matched.
+A --json-field filter is a dotted path to a field, then =, !=, <, <=, > or >= and
+a value, e.g. request.status>=500; a path on its own only needs the field to be
+there. With --json-field the query is optional, and given with -e.
+Without a path, or when the path is -, standard input is searched.
+minigrep index build writes an index of each directory (default .) for --index.";
+
+#[derive(Debug, PartialEq)]
+pub struct Config {
+    // usually just the one query, more with -e and -f
+    pub patterns: Vec<String>,
+    // all of them have to match, as well as the query
+    pub json_fields: Vec<FieldFilter>,
+    pub paths: Vec<String>,
+    pub case_sensitive: bool,
+    pub regex: bool,
+    pub word_regexp: bool,
+    pub line_regexp: bool,
+    // match the whole file at once instead of line by line
+    pub multiline: bool,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    pub invert: bool,
+    pub mode: OutputMode,
+    pub before_context: usize,
+    pub after_context: usize,
+    pub color: ColorChoice,
+    pub globs: Vec<String>,
+    pub iglobs: Vec<String>,
+    pub types: Vec<String>,
+    pub types_not: Vec<String>,
+    pub hidden: bool,
+    // compressed input is always decompressed, this skips anything else
+    pub decompress: bool,
+    // None means UTF-8, unless the file starts with a byte order mark
+    pub encoding: Option<&'static Encoding>,
+    pub binary: BinaryMode,
+    // None means only for large files, see lib::use_mmap
+    pub mmap: Option<bool>,
+    pub follow: bool,
+    // use the index of the directories searched, if they have one
+    pub index: bool,
+    // minigrep index build: index the paths instead of searching them
+    pub build_index: bool,
+    pub no_ignore: bool,
+    pub replace: Option<String>,
+    pub preview: bool,
+    pub in_place: bool,
+    // None means one thread per CPU
+    pub jobs: Option<usize>,
+    pub help: bool,
+    pub version: bool,
+}
+impl Config {
+    // takes the args iterator directly, see ConfigWithIterators in iterators_and_closures
+    pub fn new<T>(args: T) -> Result<Config, Error>
+    where
+        T: Iterator<Item = String>,
+    {
+        let case_insensitive = env::var("CASE_INSENSITIVE").is_ok();
+        Config::parse(args, case_insensitive)
+    }
+
+    // the environment is passed in so the flags can be tested without touching it
+    fn parse<T>(args: T, case_insensitive: bool) -> Result<Config, Error>
+    where
+        T: Iterator<Item = String>,
+    {
+        let mut config = Config {
+            patterns: Vec::new(),
+            json_fields: Vec::new(),
+            paths: Vec::new(),
+            case_sensitive: !case_insensitive,
+            regex: false,
+            word_regexp: false,
+            line_regexp: false,
+            multiline: false,
+            line_number: false,
+            byte_offset: false,
+            invert: false,
+            mode: OutputMode::Lines,
+            before_context: 0,
+            after_context: 0,
+            color: ColorChoice::Auto,
+            globs: Vec::new(),
+            iglobs: Vec::new(),
+            types: Vec::new(),
+            types_not: Vec::new(),
+            hidden: false,
+            decompress: false,
+            encoding: None,
+            binary: BinaryMode::Skip,
+            mmap: None,
+            follow: false,
+            index: false,
+            build_index: false,
+            no_ignore: false,
+            replace: None,
+            preview: true,
+            in_place: false,
+            jobs: None,
+            help: false,
+            version: false,
+        };
+        let mut positional = Vec::new();
+        let mut only_positional = false;
+        // with -e or -f there is no query among the positional arguments
+        let mut patterns_given = false;
+
+        // skip the program name; after it `index build` is a subcommand rather
+        // than a search for "index" in a file called build (-e index build is)
+        let mut args = args.skip(1).collect::<Vec<_>>().into_iter();
+        if matches!(args.as_slice(), [first, second, ..] if first == "index" && second == "build") {
+            config.build_index = true;
+            args.nth(1);
+        }
+
+        while let Some(arg) = args.next() {
+            if only_positional || arg == "-" || !arg.starts_with('-') {
+                positional.push(arg);
+            } else if arg == "--" {
+                only_positional = true;
+            } else if let Some(long) = arg.strip_prefix("--") {
+                // the value is either --name=value or the next argument
+                patterns_given |= matches!(long.split('=').next(), Some("regexp" | "file"));
+                match long.split_once('=') {
+                    Some((name, value)) => config.set_value(name, value)?,
+                    None if takes_value(long) => {
+                        let value = next_value(&mut args, long)?;
+                        config.set_value(long, &value)?;
+                    }
+                    None => config.set_long(long)?,
+                }
+            } else {
+                // short flags may be bundled, e.g. -in, and the last one may
+                // take the rest of the bundle as its value, e.g. -nA3
+                for (i, flag) in arg.char_indices().skip(1) {
+                    match short_value_name(flag) {
+                        Some(name) => {
+                            patterns_given |= matches!(name, "regexp" | "file");
+                            let rest = &arg[i + flag.len_utf8()..];
+                            let value = match rest.is_empty() {
+                                true => next_value(&mut args, name)?,
+                                false => rest.to_string(),
+                            };
+                            config.set_value(name, &value)?;
+                            break;
+                        }
+                        None => config.set_short(flag)?,
+                    }
+                }
+            }
+        }
+
+        // --help and --version do not need a query
+        if config.help || config.version {
+            return Ok(config);
+        }
+
+        if config.build_index {
+            config.paths = positional;
+            if config.paths.is_empty() {
+                config.paths.push(String::from("."));
+            }
+            return Ok(config);
+        }
+
+        if config.in_place && config.replace.is_none() {
+            return Err(usage("--in-place needs --replace"));
+        }
+        if config.invert && config.replace.is_some() {
+            return Err(usage("--replace cannot be used with -v"));
+        }
+
+        let mut positional = positional.into_iter();
+        // with --json-field there may be no query at all, so every positional
+        // argument is a path and the query is given with -e
+        if config.json_fields.is_empty() && !patterns_given {
+            match positional.next() {
+                Some(query) => config.patterns.push(query),
+                None => return Err(usage(format!("Not enough arguments\n\n{}", USAGE))),
+            }
+        }
+
+        // without any paths we read standard input
+        config.paths = positional.collect();
+        if config.paths.is_empty() {
+            config.paths.push(String::from("-"));
+        }
+        // a pipe already waits for more, and a file never ends, so there is
+        // only ever the one
+        if config.follow && (config.paths.len() > 1 || config.paths[0] == "-") {
+            return Err(usage("--follow needs exactly one file"));
+        }
+        // a whole line is the whole file there
+        if config.multiline && config.line_regexp {
+            return Err(usage("-x cannot be used with --multiline"));
+        }
+        // the file never ends, so it cannot all be matched at once
+        if config.multiline && config.follow {
+            return Err(usage("--multiline cannot be used with --follow"));
+        }
+        // a file is not a JSON value per line any more
+        if config.multiline && !config.json_fields.is_empty() {
+            return Err(usage("--json-field cannot be used with --multiline"));
+        }
+        if config.replace.is_some() && config.patterns.is_empty() {
+            return Err(usage("--replace needs a query to replace"));
+        }
+        // in place is done line by line
+        if config.multiline && config.in_place {
+            return Err(usage("--in-place cannot be used with --multiline"));
+        }
+        if config.follow && config.in_place {
+            return Err(usage("--in-place cannot be used with --follow"));
+        }
+        Ok(config)
+    }
+
+    fn set_long(&mut self, flag: &str) -> Result<(), Error> {
+        match flag {
+            "ignore-case" => self.case_sensitive = false,
+            "case-sensitive" => self.case_sensitive = true,
+            "line-number" => self.line_number = true,
+            "byte-offset" => self.byte_offset = true,
+            "word-regexp" => self.word_regexp = true,
+            "line-regexp" => self.line_regexp = true,
+            "multiline" => self.multiline = true,
+            "invert-match" => self.invert = true,
+            "count" => self.mode = OutputMode::Count,
+            "files-with-matches" => self.mode = OutputMode::FilesWithMatches,
+            "json" => self.mode = OutputMode::Json,
+            "regex" => self.regex = true,
+            "no-preview" => self.preview = false,
+            "in-place" => self.in_place = true,
+            "hidden" => self.hidden = true,
+            "decompress" => self.decompress = true,
+            "binary" => self.binary = BinaryMode::Report,
+            "text" => self.binary = BinaryMode::Text,
+            "mmap" => self.mmap = Some(true),
+            "no-mmap" => self.mmap = Some(false),
+            "follow" => self.follow = true,
+            "index" => self.index = true,
+            "no-ignore" => self.no_ignore = true,
+            // like grep, --color on its own is the same as --color=auto
+            "color" => self.color = ColorChoice::Auto,
+            "help" => self.help = true,
+            "version" => self.version = true,
+            _ => return Err(usage(format!("Unknown option --{}\n\n{}", flag, USAGE))),
+        }
+        Ok(())
+    }
+
+    fn set_value(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        match name {
+            "after-context" => self.after_context = parse_number(name, value)?,
+            "before-context" => self.before_context = parse_number(name, value)?,
+            "context" => {
+                self.after_context = parse_number(name, value)?;
+                self.before_context = self.after_context;
+            }
+            "regexp" => self.patterns.push(value.to_string()),
+            "json-field" => self.json_fields.push(FieldFilter::parse(value)?),
+            // blank lines would match everything, so they are left out
+            "file" => {
+                let patterns = fs::read_to_string(value).map_err(|e| Error::io(value, e))?;
+                self.patterns
+                    .extend(patterns.lines().filter(|p| !p.is_empty()).map(String::from));
+            }
+            "replace" => self.replace = Some(value.to_string()),
+            "glob" => self.globs.push(value.to_string()),
+            "iglob" => self.iglobs.push(value.to_string()),
+            "type" => self.types.push(value.to_string()),
+            "type-not" => self.types_not.push(value.to_string()),
+            "encoding" => {
+                self.encoding =
+                    Some(Encoding::for_label(value.as_bytes()).ok_or_else(|| {
+                        usage(format!("Unknown encoding for --encoding: {}", value))
+                    })?)
+            }
+            "threads" => match parse_number(name, value)? {
+                0 => return Err(usage("--threads must be at least 1")),
+                n => self.jobs = Some(n),
+            },
+            "color" => {
+                self.color = match value {
+                    "auto" => ColorChoice::Auto,
+                    "always" => ColorChoice::Always,
+                    "never" => ColorChoice::Never,
+                    _ => return Err(usage(format!("Invalid value for --color: {}", value))),
+                }
+            }
+            _ => return Err(usage(format!("Unknown option --{}\n\n{}", name, USAGE))),
+        }
+        Ok(())
+    }
+
+    fn set_short(&mut self, flag: char) -> Result<(), Error> {
+        match flag {
+            'i' => self.case_sensitive = false,
+            's' => self.case_sensitive = true,
+            'n' => self.line_number = true,
+            'b' => self.byte_offset = true,
+            'w' => self.word_regexp = true,
+            'x' => self.line_regexp = true,
+            'U' => self.multiline = true,
+            'v' => self.invert = true,
+            'z' => self.decompress = true,
+            'a' => self.binary = BinaryMode::Text,
+            'F' => self.follow = true,
+            'c' => self.mode = OutputMode::Count,
+            'l' => self.mode = OutputMode::FilesWithMatches,
+            'h' => self.help = true,
+            'V' => self.version = true,
+            _ => return Err(usage(format!("Unknown option -{}\n\n{}", flag, USAGE))),
+        }
+        Ok(())
+    }
+}
+
+fn takes_value(name: &str) -> bool {
+    matches!(
+        name,
+        "after-context"
+            | "before-context"
+            | "regexp"
+            | "file"
+            | "context"
+            | "replace"
+            | "glob"
+            | "iglob"
+            | "type"
+            | "type-not"
+            | "threads"
+            | "encoding"
+            | "json-field"
+    )
+}
+
+fn short_value_name(flag: char) -> Option<&'static str> {
+    match flag {
+        'A' => Some("after-context"),
+        'B' => Some("before-context"),
+        'C' => Some("context"),
+        'e' => Some("regexp"),
+        'f' => Some("file"),
+        'r' => Some("replace"),
+        'g' => Some("glob"),
+        't' => Some("type"),
+        'T' => Some("type-not"),
+        'j' => Some("threads"),
+        _ => None,
+    }
+}
+
+fn next_value<T>(args: &mut T, name: &str) -> Result<String, Error>
+where
+    T: Iterator<Item = String>,
+{
+    args.next()
+        .ok_or_else(|| usage(format!("Missing value for --{}\n\n{}", name, USAGE)))
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, Error> {
+    value
+        .parse()
+        .map_err(|_| usage(format!("Invalid number for --{}: {}", name, value)))
+}
+
+fn usage(message: impl Into<String>) -> Error {
+    Error::Usage(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        let mut all = vec![String::from("minigrep")];
+        all.extend(args.iter().map(|s| s.to_string()));
+        all.into_iter()
+    }
+
+    #[test]
+    fn positional() {
+        let config = Config::parse(args(&["query", "a.txt", "b.txt"]), false).unwrap();
+        assert_eq!(vec!["query"], config.patterns);
+        assert_eq!(vec!["a.txt", "b.txt"], config.paths);
+        assert!(config.case_sensitive);
+    }
+
+    #[test]
+    fn not_enough_arguments() {
+        assert!(Config::parse(args(&[]), false).is_err());
+        assert!(Config::parse(args(&["-i"]), false).is_err());
+    }
+
+    #[test]
+    fn standard_input_by_default() {
+        let config = Config::parse(args(&["query"]), false).unwrap();
+        assert_eq!(vec!["-"], config.paths);
+    }
+
+    #[test]
+    fn flags_anywhere() {
+        let config =
+            Config::parse(args(&["-n", "query", "--regex", "a.txt", "-v"]), false).unwrap();
+        assert!(config.line_number && config.regex && config.invert);
+        assert_eq!(OutputMode::Lines, config.mode);
+        assert_eq!(vec!["a.txt"], config.paths);
+    }
+
+    #[test]
+    fn bundled_short_flags() {
+        let config = Config::parse(args(&["-ic", "query", "a.txt"]), false).unwrap();
+        assert!(!config.case_sensitive);
+        assert_eq!(OutputMode::Count, config.mode);
+    }
+
+    #[test]
+    fn flags_override_environment() {
+        let config = Config::parse(args(&["query", "a.txt"]), true).unwrap();
+        assert!(!config.case_sensitive);
+        let config = Config::parse(args(&["-s", "query", "a.txt"]), true).unwrap();
+        assert!(config.case_sensitive);
+        let config = Config::parse(args(&["-i", "query", "a.txt"]), false).unwrap();
+        assert!(!config.case_sensitive);
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        let err = Config::parse(args(&["-y", "query", "a.txt"]), false).unwrap_err();
+        assert!(err.to_string().starts_with("Unknown option -y"));
+        assert!(err.to_string().contains("Usage:"));
+        let err = Config::parse(args(&["--nope", "query", "a.txt"]), false).unwrap_err();
+        assert!(matches!(err, Error::Usage(_)));
+        assert!(err.to_string().starts_with("Unknown option --nope"));
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let config = Config::parse(args(&["--", "-v", "a.txt"]), false).unwrap();
+        assert_eq!(vec!["-v"], config.patterns);
+        assert!(!config.invert);
+    }
+
+    #[test]
+    fn help_and_version_need_no_query() {
+        assert!(Config::parse(args(&["--help"]), false).unwrap().help);
+        assert!(Config::parse(args(&["-V"]), false).unwrap().version);
+    }
+
+    #[test]
+    fn context_options() {
+        let config = Config::parse(args(&["-A", "2", "-B1", "q", "a.txt"]), false).unwrap();
+        assert_eq!((1, 2), (config.before_context, config.after_context));
+
+        let config = Config::parse(args(&["-nC3", "q", "a.txt"]), false).unwrap();
+        assert!(config.line_number);
+        assert_eq!((3, 3), (config.before_context, config.after_context));
+
+        let config = Config::parse(
+            args(&["--context=4", "--after-context", "1", "q", "a.txt"]),
+            false,
+        )
+        .unwrap();
+        assert_eq!((4, 1), (config.before_context, config.after_context));
+    }
+
+    #[test]
+    fn bad_context_values() {
+        assert!(Config::parse(args(&["-A", "x", "q", "a.txt"]), false).is_err());
+        assert!(Config::parse(args(&["q", "a.txt", "-C"]), false).is_err());
+        assert!(Config::parse(args(&["--count=3", "q", "a.txt"]), false).is_err());
+    }
+
+    #[test]
+    fn color() {
+        assert_eq!(
+            ColorChoice::Auto,
+            Config::parse(args(&["q"]), false).unwrap().color
+        );
+        let config = Config::parse(args(&["--color=never", "q"]), false).unwrap();
+        assert_eq!(ColorChoice::Never, config.color);
+        let config = Config::parse(args(&["--color", "q", "a.txt"]), false).unwrap();
+        assert_eq!(ColorChoice::Auto, config.color);
+        assert_eq!(vec!["a.txt"], config.paths);
+        assert!(Config::parse(args(&["--color=sometimes", "q"]), false).is_err());
+    }
+
+    #[test]
+    fn threads() {
+        assert_eq!(None, Config::parse(args(&["q"]), false).unwrap().jobs);
+        let config = Config::parse(args(&["-j", "4", "q"]), false).unwrap();
+        assert_eq!(Some(4), config.jobs);
+        let config = Config::parse(args(&["--threads=2", "q"]), false).unwrap();
+        assert_eq!(Some(2), config.jobs);
+        assert!(Config::parse(args(&["-j0", "q"]), false).is_err());
+    }
+
+    #[test]
+    fn encoding_and_binary() {
+        let config = Config::parse(args(&["q"]), false).unwrap();
+        assert_eq!((None, BinaryMode::Skip), (config.encoding, config.binary));
+        let config = Config::parse(args(&["--encoding", "latin1", "-a", "q"]), false).unwrap();
+        assert_eq!(Some(encoding_rs::WINDOWS_1252), config.encoding);
+        assert_eq!(BinaryMode::Text, config.binary);
+        let config = Config::parse(args(&["--binary", "q"]), false).unwrap();
+        assert_eq!(BinaryMode::Report, config.binary);
+        assert!(Config::parse(args(&["--encoding=klingon", "q"]), false).is_err());
+    }
+
+    #[test]
+    fn follow() {
+        assert!(
+            Config::parse(args(&["-F", "q", "app.log"]), false)
+                .unwrap()
+                .follow
+        );
+        assert!(Config::parse(args(&["--follow", "q"]), false).is_err());
+        assert!(Config::parse(args(&["-F", "q", "a.log", "b.log"]), false).is_err());
+    }
+
+    #[test]
+    fn multiline() {
+        assert!(Config::parse(args(&["-U", "q"]), false).unwrap().multiline);
+        assert!(Config::parse(args(&["-U", "-x", "q"]), false).is_err());
+        assert!(Config::parse(args(&["-U", "-F", "q", "app.log"]), false).is_err());
+        // the help shows the \n, rather than breaking the line there
+        let help = USAGE.lines().find(|l| l.contains("--multiline")).unwrap();
+        assert!(help.ends_with(r"e.g. with \n in a --regex"));
+    }
+
+    #[test]
+    fn index_build() {
+        let config = Config::parse(args(&["index", "build", "--hidden"]), false).unwrap();
+        assert!(config.build_index && config.hidden);
+        assert_eq!(vec!["."], config.paths);
+        // only followed by build is it the subcommand
+        let config = Config::parse(args(&["--index", "index", "src"]), false).unwrap();
+        assert!(config.index && !config.build_index);
+        assert_eq!(vec!["index"], config.patterns);
+        let config = Config::parse(args(&["-e", "index", "build"]), false).unwrap();
+        assert_eq!(vec!["build"], config.paths);
+    }
+
+    #[test]
+    fn json_fields() {
+        let config =
+            Config::parse(args(&["--json-field", "level=error", "app.log"]), false).unwrap();
+        assert_eq!(1, config.json_fields.len());
+        assert!(config.patterns.is_empty());
+        assert_eq!(vec!["app.log"], config.paths);
+        let config = Config::parse(args(&["--json-field=user", "-e", "timeout"]), false).unwrap();
+        assert_eq!(vec!["timeout"], config.patterns);
+        assert!(Config::parse(args(&["--json-field", "=error"]), false).is_err());
+        assert!(Config::parse(args(&["--json-field", "user", "-U"]), false).is_err());
+        assert!(Config::parse(args(&["--json-field", "user", "-r", "x"]), false).is_err());
+    }
+
+    #[test]
+    fn mmap() {
+        assert_eq!(None, Config::parse(args(&["q"]), false).unwrap().mmap);
+        let config = Config::parse(args(&["--mmap", "q"]), false).unwrap();
+        assert_eq!(Some(true), config.mmap);
+        let config = Config::parse(args(&["--mmap", "--no-mmap", "q"]), false).unwrap();
+        assert_eq!(Some(false), config.mmap);
+    }
+
+    #[test]
+    fn file_filters() {
+        let config = Config::parse(
+            args(&[
+                "-g",
+                "*.rs",
+                "--glob=!target/*",
+                "--iglob",
+                "*.MD",
+                "-trust",
+                "q",
+            ]),
+            false,
+        )
+        .unwrap();
+        assert_eq!(vec!["*.rs", "!target/*"], config.globs);
+        assert_eq!(vec!["*.MD"], config.iglobs);
+        assert_eq!(vec!["rust"], config.types);
+        assert!(!config.hidden && !config.no_ignore);
+    }
+
+    #[test]
+    fn many_patterns() {
+        let config =
+            Config::parse(args(&["-e", "E1", "a.txt", "--regexp=E2", "-eE3"]), false).unwrap();
+        assert_eq!(vec!["E1", "E2", "E3"], config.patterns);
+        assert_eq!(vec!["a.txt"], config.paths);
+    }
+
+    #[test]
+    fn pattern_file() {
+        let dir = TempDir::new("patterns");
+        let path = dir.join("codes.txt");
+        fs::write(&path, "E1001\n\nE1002\n").unwrap();
+        let config =
+            Config::parse(args(&["-f", path.to_str().unwrap(), "-e", "E7"]), false).unwrap();
+
+        assert_eq!(vec!["E1001", "E1002", "E7"], config.patterns);
+        assert_eq!(vec!["-"], config.paths);
+        let err = Config::parse(args(&["-f", "no/such/file"]), false).unwrap_err();
+        assert!(matches!(err, Error::Io { path: Some(_), .. }));
+    }
+
+    #[test]
+    fn replace() {
+        let config = Config::parse(args(&["-r", "new", "old", "a.txt"]), false).unwrap();
+        assert_eq!(Some(String::from("new")), config.replace);
+        assert!(config.preview && !config.in_place);
+        let config = Config::parse(args(&["--replace=", "--in-place", "old"]), false).unwrap();
+        assert_eq!(Some(String::new()), config.replace);
+        assert!(config.in_place);
+
+        assert!(Config::parse(args(&["--in-place", "old", "a.txt"]), false).is_err());
+        assert!(Config::parse(args(&["-v", "-r", "new", "old"]), false).is_err());
+    }
+}
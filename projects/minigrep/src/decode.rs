@@ -0,0 +1,90 @@
+use std::io::{self, BufRead, BufReader};
+
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+// Turn the input into UTF-8 when it is in some other encoding, either the one
+// given with --encoding or, without one, the one its byte order mark says
+// (UTF-16 files from Windows usually have one).
+//
+// Anything else is searched as it is. Lines that are not valid UTF-8, like
+// the odd Latin-1 file in a tree, are decoded lossily by search_reader, so
+// they are still searched rather than stopping the run.
+pub fn decoded<'a>(
+    mut reader: Box<dyn BufRead + 'a>,
+    encoding: Option<&'static Encoding>,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    if !needs_decoding(reader.fill_buf()?, encoding) {
+        return Ok(reader);
+    }
+    // what we were told wins over a byte order mark; the decoder still drops
+    // one that says the same
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(encoding)
+        .strip_bom(encoding.is_none())
+        .build(reader);
+    Ok(Box::new(BufReader::new(decoder)))
+}
+
+// Whether the input is searched in some other bytes than its own, going by
+// its first bytes when no encoding was given
+pub fn needs_decoding(head: &[u8], encoding: Option<&'static Encoding>) -> bool {
+    match encoding {
+        Some(encoding) => encoding != encoding_rs::UTF_8,
+        None => Encoding::for_bom(head).is_some(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn decode(bytes: &'static [u8], encoding: Option<&'static Encoding>) -> String {
+        let mut text = String::new();
+        decoded(Box::new(bytes), encoding)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn utf16_with_bom() {
+        let little = b"\xff\xfeh\0\xe9\0\r\0\n\0";
+        assert_eq!("h\u{e9}\r\n", decode(little, None));
+        let big = b"\xfe\xff\0h\0\xe9\0\r\0\n";
+        assert_eq!("h\u{e9}\r\n", decode(big, None));
+        assert!(needs_decoding(little, None));
+    }
+
+    #[test]
+    fn utf8_bom_is_stripped() {
+        assert_eq!("caf\u{e9}", decode(b"\xef\xbb\xbfcaf\xc3\xa9", None));
+    }
+
+    #[test]
+    fn explicit_encoding() {
+        let latin1 = Encoding::for_label(b"latin1");
+        assert_eq!("caf\u{e9}", decode(b"caf\xe9", latin1));
+        assert!(needs_decoding(b"caf\xe9", latin1));
+        // over a byte order mark, which is just two more Latin-1 characters
+        assert_eq!("\u{ff}\u{fe}h\0", decode(b"\xff\xfeh\0", latin1));
+        assert!(!needs_decoding(b"\xff\xfeh\0", Some(encoding_rs::UTF_8)));
+        // but one that agrees is dropped
+        let utf16 = Encoding::for_label(b"utf-16le");
+        assert_eq!("h", decode(b"\xff\xfeh\0", utf16));
+    }
+
+    #[test]
+    fn everything_else_is_left_alone() {
+        let mut bytes = Vec::new();
+        decoded(Box::new(&b"caf\xe9"[..]), None)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(b"caf\xe9", &bytes[..]);
+        assert!(!needs_decoding(b"caf\xe9", None));
+        assert!(!needs_decoding(b"cafe", Some(encoding_rs::UTF_8)));
+    }
+}
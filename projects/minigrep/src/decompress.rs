@@ -0,0 +1,100 @@
+use std::io::{self, BufRead, BufReader};
+
+use flate2::bufread::MultiGzDecoder;
+use xz2::bufread::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+// The compressed formats we can search through
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+// Recognize a compressed file by its first bytes rather than by its name,
+// so rotated logs like app.log.1 are found too
+pub fn detect(head: &[u8]) -> Option<Format> {
+    if head.starts_with(&[0x1f, 0x8b]) {
+        Some(Format::Gzip)
+    } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Some(Format::Zstd)
+    } else if head.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Some(Format::Xz)
+    } else {
+        None
+    }
+}
+
+// Wrap the reader so it reads the decompressed stream. The decoders work a
+// buffer at a time, so memory stays flat however large the archive is.
+// Input that is not compressed is read as it is.
+pub fn decompressed<'a>(mut reader: Box<dyn BufRead + 'a>) -> io::Result<Box<dyn BufRead + 'a>> {
+    // a pipe might hand us fewer bytes than the magic numbers at first, but
+    // files and most pipes give a whole buffer
+    let format = detect(reader.fill_buf()?);
+    Ok(match format {
+        // gzip files can be several members one after the other, e.g. from cat a.gz b.gz
+        Some(Format::Gzip) => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Some(Format::Zstd) => Box::new(BufReader::new(ZstdDecoder::with_buffer(reader)?)),
+        // also reads concatenated xz streams, like xz -dc does
+        Some(Format::Xz) => Box::new(BufReader::new(XzDecoder::new_multi_decoder(reader))),
+        None => reader,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    const LOG: &str = "INFO started\nERROR disk full\nINFO stopped\n";
+
+    fn read_all(reader: Box<dyn BufRead>) -> String {
+        let mut text = String::new();
+        decompressed(reader)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(LOG.as_bytes()).unwrap();
+        let mut compressed = encoder.finish().unwrap();
+        assert_eq!(Some(Format::Gzip), detect(&compressed));
+
+        // a second member, like after cat a.gz b.gz
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(b"INFO again\n").unwrap();
+        compressed.extend(encoder.finish().unwrap());
+
+        let expected = format!("{}INFO again\n", LOG);
+        assert_eq!(expected, read_all(Box::new(io::Cursor::new(compressed))));
+    }
+
+    #[test]
+    fn zstd() {
+        let compressed = zstd::encode_all(LOG.as_bytes(), 1).unwrap();
+        assert_eq!(Some(Format::Zstd), detect(&compressed));
+        assert_eq!(LOG, read_all(Box::new(io::Cursor::new(compressed))));
+    }
+
+    #[test]
+    fn xz() {
+        let mut compressed = Vec::new();
+        xz2::read::XzEncoder::new(LOG.as_bytes(), 1)
+            .read_to_end(&mut compressed)
+            .unwrap();
+        assert_eq!(Some(Format::Xz), detect(&compressed));
+        assert_eq!(LOG, read_all(Box::new(io::Cursor::new(compressed))));
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(None, detect(LOG.as_bytes()));
+        assert_eq!(LOG, read_all(Box::new(LOG.as_bytes())));
+    }
+}
@@ -0,0 +1,140 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+// Everything that can go wrong, so callers of the library can tell a bad
+// argument from a file that cannot be read or a query that does not compile.
+//
+// The message of each variant is only its own part; the underlying error is
+// its source(), so main prints the whole chain.
+#[derive(Debug)]
+pub enum Error {
+    // bad command line arguments, the message says what to fix
+    Usage(String),
+    // reading a file or writing one in place failed; without a path it was
+    // writing the output that failed
+    Io {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    // a query that is not a valid regular expression, or too many of them
+    Pattern {
+        pattern: String,
+        source: Box<dyn error::Error + Send + Sync>,
+    },
+    // some files could not be searched; each of them was reported on stderr
+    // when it happened, and the others were searched all the same
+    Skipped(usize),
+}
+impl Error {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Error {
+        Error::Io {
+            path: Some(path.into()),
+            source,
+        }
+    }
+
+    pub fn pattern(pattern: &str, source: impl Into<Box<dyn error::Error + Send + Sync>>) -> Error {
+        Error::Pattern {
+            pattern: pattern.to_string(),
+            source: source.into(),
+        }
+    }
+
+    // An error about one of the files, after which the others can still be
+    // searched. Without a path it is the output that failed, and then there
+    // is no point going on.
+    pub fn is_about_a_file(&self) -> bool {
+        matches!(self, Error::Io { path: Some(_), .. })
+    }
+
+    // the error with all its sources, e.g. "poem.txt: No such file or directory"
+    pub fn describe(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = error::Error::source(self);
+        while let Some(e) = source {
+            message.push_str(&format!(": {}", e));
+            source = e.source();
+        }
+        message
+    }
+
+    // e.g. the other end of `minigrep ... | head` was closed, which is not
+    // worth complaining about
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, Error::Io { path: None, source } if source.kind() == io::ErrorKind::BrokenPipe)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(message) => write!(f, "{}", message),
+            Error::Io {
+                path: Some(path), ..
+            } => write!(f, "{}", path.display()),
+            Error::Io { path: None, .. } => write!(f, "cannot write the output"),
+            Error::Pattern { pattern, .. } => write!(f, "invalid query {:?}", pattern),
+            Error::Skipped(1) => write!(f, "1 file could not be searched"),
+            Error::Skipped(n) => write!(f, "{} files could not be searched", n),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Usage(_) | Error::Skipped(_) => None,
+            Error::Io { source, .. } => Some(source),
+            Error::Pattern { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+// the only io::Errors that are not about a file we were given
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Error {
+        Error::Io { path: None, source }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn source_chain() {
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        let err = Error::io("logs/app.log", not_found);
+        assert_eq!("logs/app.log", err.to_string());
+        assert_eq!("no such file", err.source().unwrap().to_string());
+
+        let err = Error::pattern("(", crate::build_regex("(", true).unwrap_err());
+        assert_eq!("invalid query \"(\"", err.to_string());
+        assert!(err.source().unwrap().to_string().contains("unclosed group"));
+
+        assert!(Error::Usage(String::from("Not enough arguments"))
+            .source()
+            .is_none());
+    }
+
+    #[test]
+    fn broken_pipe() {
+        let pipe = || io::Error::from(io::ErrorKind::BrokenPipe);
+        assert!(Error::from(pipe()).is_broken_pipe());
+        // only when writing the output, not some file
+        assert!(!Error::io("fifo", pipe()).is_broken_pipe());
+    }
+
+    #[test]
+    fn about_a_file() {
+        let not_found = || io::Error::from(io::ErrorKind::NotFound);
+        let err = Error::io("logs/app.log", not_found());
+        assert!(err.is_about_a_file());
+        assert!(err.describe().starts_with("logs/app.log: "));
+        assert!(!Error::from(not_found()).is_about_a_file());
+        assert!(!Error::Usage(String::from("Not enough arguments")).is_about_a_file());
+    }
+}
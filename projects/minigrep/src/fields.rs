@@ -0,0 +1,206 @@
+use std::cmp::Ordering;
+
+use caseless::Caseless;
+use serde_json::{Number, Value};
+
+use crate::Error;
+
+// A --json-field filter on JSON Lines, e.g. level=error, request.status>=500
+// or just user.id for lines that have one. Matching the raw text against
+// "level":"error" breaks as soon as the keys move or the spacing changes,
+// this looks at the parsed value instead.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FieldFilter {
+    // the keys to follow from the top, or indexes into arrays
+    path: Vec<String>,
+    op: Op,
+    value: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Op {
+    Exists,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// the two character ones first, so <= is not taken for <
+const OPS: [(&str, Op); 6] = [
+    ("!=", Op::Ne),
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("=", Op::Eq),
+    ("<", Op::Lt),
+    (">", Op::Gt),
+];
+
+impl FieldFilter {
+    pub fn parse(filter: &str) -> Result<FieldFilter, Error> {
+        let invalid = || Error::Usage(format!("Invalid filter for --json-field: {}", filter));
+        let (path, op, value) = match filter.find(['=', '!', '<', '>']) {
+            None => (filter, Op::Exists, ""),
+            Some(i) => {
+                let rest = &filter[i..];
+                let (name, op) = OPS
+                    .into_iter()
+                    .find(|(name, _)| rest.starts_with(name))
+                    .ok_or_else(invalid)?;
+                (&filter[..i], op, &rest[name.len()..])
+            }
+        };
+        if path.split('.').any(str::is_empty) {
+            return Err(invalid());
+        }
+        Ok(FieldFilter {
+            path: path.split('.').map(String::from).collect(),
+            op,
+            value: value.to_string(),
+        })
+    }
+
+    // A field that is missing is not equal to anything, and not less or
+    // greater either. An array matches when any of its items does, so
+    // tags=slow finds "tags":["db","slow"].
+    pub fn matches(&self, json: &Value, case_sensitive: bool) -> bool {
+        let found = self.path.iter().try_fold(json, |value, key| match value {
+            Value::Object(fields) => fields.get(key),
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => None,
+        });
+        let holds = |value: &Value| {
+            let ordering = self.compare(value, case_sensitive);
+            match self.op {
+                Op::Exists => true,
+                Op::Eq | Op::Ne => ordering == Some(Ordering::Equal),
+                Op::Lt => ordering == Some(Ordering::Less),
+                Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                Op::Gt => ordering == Some(Ordering::Greater),
+                Op::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+            }
+        };
+        let any = match found {
+            None => false,
+            Some(Value::Array(items)) if self.op != Op::Exists => items.iter().any(holds),
+            Some(value) => holds(value),
+        };
+        match self.op {
+            Op::Ne => !any,
+            _ => any,
+        }
+    }
+
+    // Numbers are compared as numbers, so 500 is more than 60, and strings
+    // as strings, which also orders ISO 8601 timestamps. None when the two
+    // cannot be compared, e.g. a number with "abc".
+    fn compare(&self, value: &Value, case_sensitive: bool) -> Option<Ordering> {
+        match value {
+            Value::String(s) if case_sensitive => Some(s.as_str().cmp(&self.value)),
+            // like the query, -i folds the case of both
+            Value::String(s) => Some(
+                s.chars()
+                    .default_case_fold()
+                    .cmp(self.value.chars().default_case_fold()),
+            ),
+            Value::Number(n) => compare_numbers(n, &self.value),
+            Value::Bool(b) => Some(b.cmp(&self.value.parse::<bool>().ok()?)),
+            Value::Null => (self.value == "null").then_some(Ordering::Equal),
+            Value::Array(_) | Value::Object(_) => None,
+        }
+    }
+}
+
+// Integers as integers when both are, since as f64 ids above 2^53 would
+// compare equal to their neighbours; anything else as f64.
+fn compare_numbers(n: &Number, value: &str) -> Option<Ordering> {
+    if let (Some(a), Ok(b)) = (n.as_i64(), value.parse::<i64>()) {
+        return Some(a.cmp(&b));
+    }
+    if let (Some(a), Ok(b)) = (n.as_u64(), value.parse::<u64>()) {
+        return Some(a.cmp(&b));
+    }
+    n.as_f64()?.partial_cmp(&value.parse::<f64>().ok()?)
+}
+
+// Whether the line is JSON that passes every filter. Lines that are not JSON
+// never do.
+pub fn all_match(filters: &[FieldFilter], line: &str, case_sensitive: bool) -> bool {
+    match serde_json::from_str::<Value>(line) {
+        Ok(json) => filters.iter().all(|f| f.matches(&json, case_sensitive)),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = r#"{"level":"ERROR","msg":"timeout","request":{"status":504,"ms":30000.5},"tags":["db","slow"],"user":null}"#;
+
+    fn matches(filter: &str, case_sensitive: bool) -> bool {
+        all_match(&[FieldFilter::parse(filter).unwrap()], LINE, case_sensitive)
+    }
+
+    #[test]
+    fn parse() {
+        let filter = FieldFilter::parse("request.status>=500").unwrap();
+        assert_eq!(vec!["request", "status"], filter.path);
+        assert_eq!((Op::Ge, "500"), (filter.op, filter.value.as_str()));
+        // the value may contain operators of its own
+        let filter = FieldFilter::parse("query=a<b").unwrap();
+        assert_eq!((Op::Eq, "a<b"), (filter.op, filter.value.as_str()));
+        assert_eq!(Op::Exists, FieldFilter::parse("user").unwrap().op);
+        for invalid in ["=error", "a..b=1", "level!error", "request."] {
+            assert!(FieldFilter::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn values() {
+        assert!(matches("level=ERROR", true));
+        assert!(!matches("level=error", true));
+        assert!(matches("level=error", false));
+        assert!(matches("level!=warn", true));
+        assert!(matches("request.status>=500", true));
+        assert!(matches("request.status=504.0", true));
+        assert!(!matches("request.status<100", true));
+        assert!(matches("request.ms>9999", true));
+        assert!(matches("tags=slow", true));
+        assert!(matches("tags.0=db", true));
+        assert!(!matches("tags!=slow", true));
+        assert!(matches("user=null", true));
+        assert!(matches("user", true));
+    }
+
+    #[test]
+    fn large_integers() {
+        // 2^53 + 1, which is 2^53 again as an f64
+        let line = r#"{"id":9007199254740993,"max":18446744073709551615,"low":-9007199254740993}"#;
+        let matches = |filter| all_match(&[FieldFilter::parse(filter).unwrap()], line, true);
+        assert!(matches("id=9007199254740993"));
+        assert!(!matches("id=9007199254740992"));
+        assert!(matches("id>9007199254740992"));
+        assert!(matches("max=18446744073709551615"));
+        assert!(matches("max>18446744073709551614"));
+        assert!(matches("max>-1"));
+        assert!(matches("low<-9007199254740992"));
+        assert!(matches("low<18446744073709551615"));
+        // and against a fraction as before
+        assert!(matches("id>1.5"));
+    }
+
+    #[test]
+    fn missing_fields_and_other_lines() {
+        assert!(!matches("host=web1", true));
+        assert!(matches("host!=web1", true));
+        assert!(!matches("host", true));
+        // not a number, so neither more nor less
+        assert!(!matches("request.status>abc", true));
+        assert!(!matches("request.status<abc", true));
+        let level = [FieldFilter::parse("level=error").unwrap()];
+        assert!(!all_match(&level, "level=error", false));
+    }
+}
@@ -0,0 +1,152 @@
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+// A file that never ends, for --follow: at the end it waits for more to be
+// written instead of returning 0, like tail -F. The search on top of it does
+// not know the difference, so it prints new lines as they come in.
+//
+// Logs get truncated (logrotate's copytruncate) or moved away and replaced by
+// a new file, so at the end of the file we also check for both:
+// a file shorter than what we have read was truncated, and a path that now
+// belongs to another file (its inode changed) was rotated.
+pub struct Follow {
+    path: PathBuf,
+    file: File,
+    // which file the path pointed to when we opened it
+    id: FileId,
+    // how far we have read
+    pos: u64,
+    poll: Duration,
+}
+
+// tail checks once a second, but on call a quarter is nicer
+const POLL: Duration = Duration::from_millis(250);
+
+impl Follow {
+    pub fn new(path: &Path, file: File) -> io::Result<Follow> {
+        Follow::with_poll(path, file, POLL)
+    }
+
+    fn with_poll(path: &Path, file: File, poll: Duration) -> io::Result<Follow> {
+        Ok(Follow {
+            path: path.to_path_buf(),
+            id: file_id(&file.metadata()?),
+            file,
+            pos: 0,
+            poll,
+        })
+    }
+}
+
+impl Read for Follow {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.file.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                self.pos += read as u64;
+                return Ok(read);
+            }
+
+            if self.file.metadata()?.len() < self.pos {
+                // truncated, so everything in it is new
+                self.file.seek(SeekFrom::Start(0))?;
+                self.pos = 0;
+                continue;
+            }
+            match fs::metadata(&self.path) {
+                // rotated: the old file has been read to its end just now, so
+                // carry on with the new one from its start
+                Ok(metadata) if file_id(&metadata) != self.id => {
+                    self.file = File::open(&self.path)?;
+                    self.id = file_id(&self.file.metadata()?);
+                    self.pos = 0;
+                    continue;
+                }
+                // nothing new, or moved away and not created again yet
+                _ => thread::sleep(self.poll),
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+type FileId = (u64, u64);
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> FileId {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+// without inodes we cannot tell a rotated file from the old one, but
+// truncation is still noticed
+#[cfg(not(unix))]
+type FileId = ();
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> FileId {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::sync::mpsc;
+
+    use crate::testing::TempDir;
+
+    // read lines from the followed file on another thread, since it never ends
+    fn follow(path: &Path) -> mpsc::Receiver<String> {
+        let file = File::open(path).unwrap();
+        let follow = Follow::with_poll(path, file, Duration::from_millis(10)).unwrap();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(follow).lines() {
+                if tx.send(line.unwrap()).is_err() {
+                    return;
+                }
+            }
+        });
+        rx
+    }
+
+    fn next(rx: &mpsc::Receiver<String>) -> String {
+        rx.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = File::options().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn appended_truncated_and_rotated() {
+        let dir = TempDir::new("follow");
+        let path = dir.join("app.log");
+        fs::write(&path, "first\n").unwrap();
+
+        let rx = follow(&path);
+        assert_eq!("first", next(&rx));
+
+        // a line written in two goes is still one line
+        append(&path, "sec");
+        thread::sleep(Duration::from_millis(50));
+        append(&path, "ond\n");
+        assert_eq!("second", next(&rx));
+
+        // copytruncate
+        fs::write(&path, "").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        append(&path, "after truncate\n");
+        assert_eq!("after truncate", next(&rx));
+
+        // moved away, with a last line written to the old file, then a new one
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        append(&dir.join("app.log.1"), "last of old\n");
+        fs::write(&path, "new file\n").unwrap();
+        assert_eq!("last of old", next(&rx));
+        assert_eq!("new file", next(&rx));
+    }
+}
@@ -0,0 +1,699 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use memmap2::Mmap;
+use regex_syntax::hir::literal::Extractor;
+use regex_syntax::ParserBuilder;
+
+use crate::{archive, decode, decompress, parallel, walk, Config, Error, OutputMode};
+
+// A trigram index of a directory, for --index: which files contain which
+// three bytes in a row. A match has to contain every trigram of the literal it
+// starts with, so the files missing any of them need not be searched at all.
+// The files that might match are then searched as usual, so the index only
+// ever decides what to skip, never what matched.
+//
+// `minigrep index build <dir>` writes it into <dir>/.minigrep-index, and
+// running it again only reads the files whose modification time or size
+// changed since. A file that changed after the index was built is searched
+// whatever the index says, so a stale index makes the search slower, not wrong.
+pub const INDEX_FILE: &str = ".minigrep-index";
+
+// the version is part of it, so an index from another version is just rebuilt
+const MAGIC: &[u8; 8] = b"MGINDEX1";
+
+// The trigrams are lowercase (ASCII only), so one index serves -i too
+fn trigram(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([
+        0,
+        bytes[0].to_ascii_lowercase(),
+        bytes[1].to_ascii_lowercase(),
+        bytes[2].to_ascii_lowercase(),
+    ])
+}
+
+// Which version of a file the index saw
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Stamp {
+    secs: u64,
+    nanos: u32,
+    size: u64,
+}
+impl Stamp {
+    fn of(metadata: &fs::Metadata) -> io::Result<Stamp> {
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH);
+        let modified = modified.unwrap_or_default();
+        Ok(Stamp {
+            secs: modified.as_secs(),
+            nanos: modified.subsec_nanos(),
+            size: metadata.len(),
+        })
+    }
+
+    // Changed so soon before the index was built that a change right after it
+    // could leave the same modification time behind, on a file system that
+    // only keeps whole seconds. Git calls these racily clean.
+    fn is_racy(&self, started: SystemTime) -> bool {
+        let modified = UNIX_EPOCH + Duration::new(self.secs, self.nanos);
+        modified + Duration::from_secs(1) >= started
+    }
+}
+
+#[derive(Debug)]
+struct FileEntry {
+    // relative to the indexed directory
+    path: String,
+    stamp: Stamp,
+    // false for the files that are always searched: compressed or re-encoded
+    // ones and archives, whose bytes on disk are not the text, and racy ones
+    indexed: bool,
+}
+
+// What `index build` did
+pub struct Built {
+    pub files: usize,
+    // the rest were unchanged and taken over from the old index
+    pub read: usize,
+}
+
+pub fn build(dir: &str, config: &Config, jobs: usize) -> Result<Built, Error> {
+    let root = Path::new(dir);
+    if !root.is_dir() {
+        return Err(Error::Usage(format!(
+            "index build needs a directory: {}",
+            dir
+        )));
+    }
+    let started = SystemTime::now();
+    // a missing or broken index is simply built from scratch
+    let old = Index::open(root).ok();
+
+    let mut files = Vec::new();
+    let mut reused = HashMap::new();
+    let mut to_read = Vec::new();
+    // an index with a file missing would leave it out of every search
+    for file in walk::files_in(&[dir.to_string()], config, Err)? {
+        // the walk only finds the index itself with --hidden; paths that are
+        // not UTF-8 are left out, so they are always searched
+        let path = match file.strip_prefix(root).ok().and_then(|p| p.to_str()) {
+            Some(path) if path != INDEX_FILE => path.to_string(),
+            _ => continue,
+        };
+        let metadata = fs::metadata(&file).map_err(|e| Error::io(&file, e))?;
+        let stamp = Stamp::of(&metadata).map_err(|e| Error::io(&file, e))?;
+        let id = files.len() as u32;
+        // the bytes of an archive are not the text of its files either
+        let indexed = !stamp.is_racy(started) && archive::kind(&file).is_none();
+        match old.as_ref().and_then(|old| old.lookup(&path)) {
+            Some((old_id, entry)) if entry.indexed && entry.stamp == stamp => {
+                reused.insert(old_id, id);
+            }
+            _ if indexed => to_read.push((id, file)),
+            _ => {}
+        }
+        files.push(FileEntry {
+            path,
+            stamp,
+            indexed,
+        });
+    }
+
+    let mut postings: HashMap<u32, Vec<u32>> = HashMap::new();
+    if let (Some(old), false) = (&old, reused.is_empty()) {
+        old.for_each_posting(|trigram, old_id| {
+            if let Some(&id) = reused.get(&old_id) {
+                postings.entry(trigram).or_default().push(id);
+            }
+        })
+        .map_err(|e| Error::io(root.join(INDEX_FILE), e))?;
+    }
+    parallel::for_each_in_order(
+        &to_read,
+        jobs,
+        |(id, file)| {
+            let trigrams = File::open(file).and_then(|f| trigrams(BufReader::new(f)));
+            (*id, trigrams.map_err(|e| Error::io(file, e)))
+        },
+        |(id, trigrams)| {
+            match trigrams? {
+                Some(trigrams) => {
+                    for trigram in trigrams {
+                        postings.entry(trigram).or_default().push(id);
+                    }
+                }
+                None => files[id as usize].indexed = false,
+            }
+            Ok::<(), Error>(())
+        },
+    )?;
+
+    let read = to_read.len();
+    write(root, &files, postings).map_err(|e| Error::io(root.join(INDEX_FILE), e))?;
+    Ok(Built {
+        files: files.len(),
+        read,
+    })
+}
+
+// Every trigram in the file, sorted. None when the bytes on disk are not the
+// text we would search, i.e. when the file is compressed or in another encoding.
+fn trigrams<R: BufRead>(mut reader: R) -> io::Result<Option<Vec<u32>>> {
+    let head = reader.fill_buf()?;
+    if decompress::detect(head).is_some() || decode::needs_decoding(head, None) {
+        return Ok(None);
+    }
+
+    // a large file has many repeats, so they are thrown out every now and
+    // then rather than all kept until the end
+    let mut trigrams = Vec::new();
+    let mut compact_at = 1 << 20;
+    let mut window = [0u8; 3];
+    let mut seen = 0usize;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        for &byte in buf {
+            window = [window[1], window[2], byte];
+            seen += 1;
+            if seen >= 3 {
+                trigrams.push(trigram(&window));
+            }
+        }
+        let len = buf.len();
+        reader.consume(len);
+        if trigrams.len() >= compact_at {
+            trigrams.sort_unstable();
+            trigrams.dedup();
+            compact_at = (trigrams.len() * 2).max(compact_at);
+        }
+    }
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    Ok(Some(trigrams))
+}
+
+// The layout, all numbers little endian:
+//
+//     MAGIC
+//     file count: u32
+//     per file: path length: u32, path, seconds: u64, nanoseconds: u32,
+//               size: u64, indexed: u8
+//     trigram count: u32
+//     per trigram, sorted: trigram: u32, postings offset: u64, file count: u32
+//     postings: per trigram the ids of its files, ascending, as varint deltas
+//
+// The trigrams have a fixed size so a search can look them up with a binary
+// search, without reading the rest.
+const TRIGRAM_SIZE: usize = 16;
+
+fn write(root: &Path, files: &[FileEntry], postings: HashMap<u32, Vec<u32>>) -> io::Result<()> {
+    // written next to it and renamed, so a search never sees half an index
+    let path = root.join(INDEX_FILE);
+    let partial = path.with_extension("partial");
+    let mut out = BufWriter::new(File::create(&partial)?);
+
+    out.write_all(MAGIC)?;
+    out.write_all(&(files.len() as u32).to_le_bytes())?;
+    for file in files {
+        out.write_all(&(file.path.len() as u32).to_le_bytes())?;
+        out.write_all(file.path.as_bytes())?;
+        out.write_all(&file.stamp.secs.to_le_bytes())?;
+        out.write_all(&file.stamp.nanos.to_le_bytes())?;
+        out.write_all(&file.stamp.size.to_le_bytes())?;
+        out.write_all(&[file.indexed as u8])?;
+    }
+
+    let mut postings: Vec<_> = postings.into_iter().collect();
+    postings.sort_unstable_by_key(|(trigram, _)| *trigram);
+    let mut data = Vec::new();
+    out.write_all(&(postings.len() as u32).to_le_bytes())?;
+    for (trigram, mut ids) in postings {
+        // the ones taken over from the old index come first
+        ids.sort_unstable();
+        out.write_all(&trigram.to_le_bytes())?;
+        out.write_all(&(data.len() as u64).to_le_bytes())?;
+        out.write_all(&(ids.len() as u32).to_le_bytes())?;
+        let mut last = 0;
+        for id in ids {
+            write_varint(&mut data, id - last);
+            last = id;
+        }
+    }
+    out.write_all(&data)?;
+
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&partial, &path)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u32) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+// An index as read back from disk
+struct Index {
+    map: Mmap,
+    files: Vec<FileEntry>,
+    by_path: HashMap<String, u32>,
+    // where the trigrams and the postings start in `map`
+    trigrams: usize,
+    trigram_count: usize,
+    postings: usize,
+}
+impl Index {
+    fn open(root: &Path) -> io::Result<Index> {
+        let file = File::open(root.join(INDEX_FILE))?;
+        // only ever replaced by a rename, never written in place, so unlike the
+        // files we search it cannot be truncated under us
+        let map = unsafe { Mmap::map(&file) }?;
+
+        let mut cursor = Cursor {
+            bytes: &map,
+            pos: 0,
+        };
+        if cursor.take(MAGIC.len())? != MAGIC {
+            return Err(invalid());
+        }
+        let count = cursor.u32()? as usize;
+        let mut files = Vec::new();
+        for _ in 0..count {
+            let len = cursor.u32()? as usize;
+            let path = std::str::from_utf8(cursor.take(len)?).map_err(|_| invalid())?;
+            files.push(FileEntry {
+                path: path.to_string(),
+                stamp: Stamp {
+                    secs: cursor.u64()?,
+                    nanos: cursor.u32()?,
+                    size: cursor.u64()?,
+                },
+                indexed: cursor.take(1)?[0] != 0,
+            });
+        }
+        let trigram_count = cursor.u32()? as usize;
+        let trigrams = cursor.pos;
+        cursor.take(trigram_count * TRIGRAM_SIZE)?;
+        let postings = cursor.pos;
+
+        let by_path = files
+            .iter()
+            .enumerate()
+            .map(|(id, file)| (file.path.clone(), id as u32))
+            .collect();
+        Ok(Index {
+            map,
+            files,
+            by_path,
+            trigrams,
+            trigram_count,
+            postings,
+        })
+    }
+
+    fn lookup(&self, path: &str) -> Option<(u32, &FileEntry)> {
+        let id = *self.by_path.get(path)?;
+        Some((id, &self.files[id as usize]))
+    }
+
+    // the trigram, where its postings start and how many there are
+    fn trigram_at(&self, i: usize) -> (u32, usize, usize) {
+        let entry = &self.map[self.trigrams + i * TRIGRAM_SIZE..][..TRIGRAM_SIZE];
+        let number = |range: std::ops::Range<usize>| {
+            entry[range]
+                .iter()
+                .rev()
+                .fold(0u64, |n, &byte| n << 8 | byte as u64)
+        };
+        (
+            number(0..4) as u32,
+            number(4..12) as usize,
+            number(12..16) as usize,
+        )
+    }
+
+    // the ids of the files that contain the trigram
+    fn files_with(&self, trigram: u32) -> io::Result<Vec<u32>> {
+        let (mut low, mut high) = (0, self.trigram_count);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.trigram_at(mid) {
+                (t, offset, count) if t == trigram => return self.postings_at(offset, count),
+                (t, _, _) if t < trigram => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn postings_at(&self, offset: usize, count: usize) -> io::Result<Vec<u32>> {
+        let mut cursor = Cursor {
+            bytes: &self.map,
+            pos: self.postings.checked_add(offset).ok_or_else(invalid)?,
+        };
+        let mut ids = Vec::with_capacity(count);
+        let mut last = 0u32;
+        for _ in 0..count {
+            last = last.checked_add(cursor.varint()?).ok_or_else(invalid)?;
+            if last as usize >= self.files.len() {
+                return Err(invalid());
+            }
+            ids.push(last);
+        }
+        Ok(ids)
+    }
+
+    fn for_each_posting<F: FnMut(u32, u32)>(&self, mut f: F) -> io::Result<()> {
+        for i in 0..self.trigram_count {
+            let (trigram, offset, count) = self.trigram_at(i);
+            for id in self.postings_at(offset, count)? {
+                f(trigram, id);
+            }
+        }
+        Ok(())
+    }
+
+    // Which files might match, by id: the ones that have all the trigrams of
+    // at least one of the alternatives
+    fn candidates(&self, alternatives: &[Vec<u32>]) -> io::Result<Vec<bool>> {
+        let mut candidates = vec![false; self.files.len()];
+        for trigrams in alternatives {
+            let mut ids: Option<Vec<u32>> = None;
+            for &trigram in trigrams {
+                let with = self.files_with(trigram)?;
+                let left = match ids.take() {
+                    Some(mut ids) => {
+                        ids.retain(|id| with.binary_search(id).is_ok());
+                        ids
+                    }
+                    None => with,
+                };
+                if left.is_empty() {
+                    break;
+                }
+                ids = Some(left);
+            }
+            for id in ids.unwrap_or_default() {
+                candidates[id as usize] = true;
+            }
+        }
+        Ok(candidates)
+    }
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).ok_or_else(invalid)?;
+        let bytes = self.bytes.get(self.pos..end).ok_or_else(invalid)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn varint(&mut self) -> io::Result<u32> {
+        let mut n = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.take(1)?[0];
+            n |= ((byte & 0x7f) as u32)
+                .checked_shl(shift)
+                .ok_or_else(invalid)?;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(invalid())
+    }
+}
+
+fn invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "not a minigrep index")
+}
+
+// With -i a match can differ from the query in more than the case of ASCII
+// letters, which the index already ignores: the Kelvin sign folds to k, and
+// the ligature ﬁ to fi. So only trigrams without any of the letters that some
+// other character folds to can be looked up then.
+const FOLDED_INTO: &[u8] = b"afhijklnstwy";
+
+fn usable_with_ignore_case(bytes: &[u8]) -> bool {
+    bytes
+        .iter()
+        .all(|b| b.is_ascii() && !FOLDED_INTO.contains(&b.to_ascii_lowercase()))
+}
+
+// The trigrams a file must have for the query to match in it: all of those of
+// at least one of the alternatives. None when the index cannot tell, and every
+// file has to be searched.
+fn required(config: &Config) -> Option<Vec<Vec<u32>>> {
+    // the index is of the bytes on disk, which are not the text in another encoding
+    let other_encoding = config
+        .encoding
+        .is_some_and(|encoding| encoding != encoding_rs::UTF_8);
+    if config.invert || config.patterns.is_empty() || other_encoding {
+        return None;
+    }
+    // -c and --json report on every file searched, also those without a
+    // match, so none can be left out
+    if matches!(config.mode, OutputMode::Count | OutputMode::Json) {
+        return None;
+    }
+
+    // the literals any match has to start with, from all the queries at once
+    let alternation = config
+        .patterns
+        .iter()
+        .map(|p| match config.regex {
+            true => format!("(?:{})", p),
+            false => regex_syntax::escape(p),
+        })
+        .collect::<Vec<_>>()
+        .join("|");
+    let hir = ParserBuilder::new()
+        .multi_line(config.multiline)
+        .build()
+        .parse(&alternation)
+        .ok()?;
+    let literals = Extractor::new().extract(&hir);
+    let literals = literals.literals()?;
+    if literals.is_empty() {
+        return None;
+    }
+
+    literals
+        .iter()
+        .map(|literal| {
+            let mut trigrams: Vec<u32> = literal
+                .as_bytes()
+                .windows(3)
+                .filter(|w| config.case_sensitive || usable_with_ignore_case(w))
+                .map(trigram)
+                .collect();
+            trigrams.sort_unstable();
+            trigrams.dedup();
+            // too short to tell anything, so any file could match
+            (!trigrams.is_empty()).then_some(trigrams)
+        })
+        .collect()
+}
+
+// Leave out the files that cannot match according to the index of the
+// directory they are in (or one above it). Files the index does not know, or
+// that changed since it was built, are kept.
+pub fn filter(config: &Config, files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let Some(required) = required(config) else {
+        return files;
+    };
+    // each path on the command line may be under a different index, or none
+    let indexes: Vec<_> = config
+        .paths
+        .iter()
+        .map(|path| find(Path::new(path), &required))
+        .collect();
+
+    files
+        .into_iter()
+        .filter(|file| {
+            let found = config
+                .paths
+                .iter()
+                .zip(&indexes)
+                .find(|(path, _)| file.starts_with(path));
+            let Some((path, Some((index, prefix, candidates)))) = found else {
+                return true;
+            };
+            let rest = file.strip_prefix(path).unwrap_or(file);
+            let key = match rest.as_os_str().is_empty() {
+                true => prefix.clone(),
+                false => prefix.join(rest),
+            };
+            let entry = key.to_str().and_then(|key| index.lookup(key));
+            match entry {
+                Some((id, entry)) if entry.indexed => {
+                    let stamp = fs::metadata(file).and_then(|m| Stamp::of(&m));
+                    candidates[id as usize] || stamp.ok() != Some(entry.stamp)
+                }
+                _ => true,
+            }
+        })
+        .collect()
+}
+
+// The index for a path on the command line, where the path is within the
+// indexed directory, and which of its files might match
+fn find(path: &Path, required: &[Vec<u32>]) -> Option<(Index, PathBuf, Vec<bool>)> {
+    if path == Path::new("-") {
+        return None;
+    }
+    let path = fs::canonicalize(path).ok()?;
+    for root in path.ancestors() {
+        if !root.join(INDEX_FILE).is_file() {
+            continue;
+        }
+        let index = Index::open(root).ok()?;
+        let candidates = index.candidates(required).ok()?;
+        let prefix = path.strip_prefix(root).ok()?.to_path_buf();
+        return Some((index, prefix, candidates));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use caseless::Caseless;
+    use std::iter;
+
+    use crate::testing::{config, TempDir};
+
+    fn trigrams_of(text: &str) -> Vec<u32> {
+        let mut trigrams: Vec<u32> = text.as_bytes().windows(3).map(trigram).collect();
+        trigrams.sort_unstable();
+        trigrams
+    }
+
+    // written `age` seconds ago, so the index does not take it for racy
+    fn write_old(path: &Path, text: &str, age: u64) {
+        fs::write(path, text).unwrap();
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(age))
+            .unwrap();
+    }
+
+    #[test]
+    fn required_trigrams() {
+        assert_eq!(
+            Some(vec![trigrams_of("nobody")]),
+            required(&config(&["nobody"]))
+        );
+        assert_eq!(
+            Some(vec![trigrams_of("error"), trigrams_of("panic")]),
+            required(&config(&["--regex", r"(error|panic)\d*"]))
+        );
+        // the index does not help with these
+        assert_eq!(None, required(&config(&["-v", "nobody"])));
+        assert_eq!(None, required(&config(&["-c", "nobody"])));
+        assert_eq!(None, required(&config(&["--json", "nobody"])));
+        assert!(required(&config(&["-l", "nobody"])).is_some());
+        assert_eq!(None, required(&config(&["no"])));
+        assert_eq!(None, required(&config(&["--regex", r"\w+body"])));
+        assert_eq!(None, required(&config(&["-e", "nobody", "-e", "id"])));
+        // not "ors" with -i, since ſ folds to s
+        assert_eq!(
+            Some(vec![trigrams_of("error")]),
+            required(&config(&["-i", "Errors"]))
+        );
+        assert_eq!(None, required(&config(&["-i", "kiss"])));
+    }
+
+    #[test]
+    fn folded_into_ascii() {
+        // every ASCII letter that a character other than itself (or its
+        // uppercase) folds to
+        for c in (0x80..=0x10ffff).filter_map(char::from_u32) {
+            for folded in iter::once(c).default_case_fold() {
+                if folded.is_ascii_alphabetic() {
+                    assert!(FOLDED_INTO.contains(&(folded as u8)), "{} {}", c, folded);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn build_and_filter() {
+        let root = TempDir::new("index");
+        fs::create_dir_all(root.join("src")).unwrap();
+        write_old(&root.join("poem.txt"), "I'm nobody! Who are you?\n", 3600);
+        write_old(&root.join("src/lib.rs"), "pub fn run() {}\n", 3600);
+        write_old(&root.join("src/main.rs"), "fn main() {}\n", 3600);
+        let dir = root.to_str().unwrap();
+
+        let built = build(dir, &config(&["index", "build", dir]), 2).unwrap();
+        assert_eq!((3, 3), (built.files, built.read));
+
+        let search = |args: &[&str]| -> Vec<String> {
+            let config = config(args);
+            filter(&config, walk::files(&config, Err).unwrap())
+                .iter()
+                .map(|p| p.strip_prefix(&root).unwrap().to_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(vec!["poem.txt"], search(&["nobody", dir]));
+        assert_eq!(vec!["src/lib.rs"], search(&["-i", "PUB", dir]));
+        assert!(search(&["somebody", dir]).is_empty());
+        // the index is found from a directory inside it too
+        let src = root.join("src");
+        assert_eq!(
+            vec!["src/main.rs"],
+            search(&["main", src.to_str().unwrap()])
+        );
+
+        // changed and new files are searched until the index is rebuilt
+        write_old(&root.join("src/lib.rs"), "// nobody\n", 1800);
+        write_old(&root.join("src/new.rs"), "// nobody\n", 1800);
+        assert_eq!(
+            vec!["poem.txt", "src/lib.rs", "src/new.rs"],
+            search(&["nobody", dir])
+        );
+        let built = build(dir, &config(&["index", "build", dir]), 2).unwrap();
+        assert_eq!((4, 2), (built.files, built.read));
+        assert_eq!(
+            vec!["poem.txt", "src/lib.rs", "src/new.rs"],
+            search(&["nobody", dir])
+        );
+        assert_eq!(vec!["src/main.rs"], search(&["main", dir]));
+
+        // a file just written might still change without its time changing,
+        // so it is searched until a later build
+        fs::write(root.join("src/main.rs"), "fn other() {}\n").unwrap();
+        let built = build(dir, &config(&["index", "build", dir]), 2).unwrap();
+        assert_eq!((4, 0), (built.files, built.read));
+        assert_eq!(
+            vec!["poem.txt", "src/lib.rs", "src/main.rs", "src/new.rs"],
+            search(&["nobody", dir])
+        );
+    }
+
+    #[test]
+    fn broken_index() {
+        let root = TempDir::new("index-broken");
+        fs::write(root.join(INDEX_FILE), "MGINDEX1\x05").unwrap();
+        assert!(Index::open(&root).is_err());
+    }
+}
@@ -1,69 +1,434 @@
-use std::env;
-use std::error::Error;
-use std::fs;
-
-pub struct Config {
-    pub query: String,
-    pub filename: String,
-    pub case_sensitive: bool,
-}
-impl Config {
-    pub fn new(args: &[String]) -> Result<Config, &'static str> {
-        match args.len() {
-            0 | 1 | 2 => Err("Not enough arguments"),
-            _ => {
-                let query = args[1].clone();
-                let filename = args[2].clone();
-                // we ignore any additional args
-
-                // get case sensitivity from env
-                let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
-                Ok(Config { query, filename, case_sensitive })
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::thread;
+
+mod archive;
+mod config;
+mod decode;
+mod decompress;
+mod error;
+mod fields;
+mod follow;
+mod index;
+mod matcher;
+mod parallel;
+mod printer;
+mod replace;
+mod searcher;
+#[cfg(test)]
+mod testing;
+mod walk;
+
+pub use config::{Config, USAGE};
+pub use error::Error;
+pub use fields::FieldFilter;
+pub use matcher::{build_regex, Matcher, QueryMatcher, Span};
+pub use printer::{BinaryMode, ColorChoice, OutputMode, Printer};
+pub use replace::{replace_in_file, replace_spans, Recorder};
+pub use searcher::{Searcher, Sink};
+
+use follow::Follow;
+use memmap2::Mmap;
+use regex::Regex;
+
+// Returns whether anything matched, which main turns into grep's exit status
+pub fn run(config: Config) -> Result<bool, Error> {
+    if config.help {
+        println!("{}", USAGE);
+        return Ok(true);
+    }
+    if config.version {
+        println!("minigrep {}", env!("CARGO_PKG_VERSION"));
+        return Ok(true);
+    }
+
+    let jobs = config
+        .jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+
+    if config.build_index {
+        for dir in &config.paths {
+            let built = index::build(dir, &config, jobs)?;
+            println!(
+                "{}: indexed {} files, {} of them read again",
+                dir, built.files, built.read
+            );
+        }
+        return Ok(true);
+    }
+
+    // compile the pattern before touching the file, so a bad pattern is reported
+    // as an error rather than a panic halfway through the search
+    let matcher = QueryMatcher::new(&config)?;
+
+    // like grep, a file that cannot be read is reported and the others are
+    // searched all the same
+    let mut errors = Errors::default();
+    let mut files = walk::files(&config, |e| errors.report(e))?;
+    if config.index {
+        files = index::filter(&config, files);
+    }
+    if config.follow && files.len() != 1 {
+        return Err(Error::Usage(String::from(
+            "--follow needs exactly one file",
+        )));
+    }
+    // only prefix the lines with the path when there can be more than one file
+    let show_path = config.paths.len() > 1
+        || config
+            .paths
+            .iter()
+            .any(|p| Path::new(p).is_dir() || archive::kind(Path::new(p)).is_some());
+
+    let color = config.color.use_color(io::stdout().is_terminal());
+    let mut printer = Printer::new(io::stdout().lock(), &config, show_path, color);
+
+    // a single file (or standard input) is printed as it is read, so the output
+    // of a pipe shows up straight away
+    if jobs == 1 || files.len() == 1 {
+        for file in &files {
+            if let Err(e) = search_file(file, &matcher, &config, &mut printer) {
+                errors.report(e)?;
             }
         }
+        printer.summary()?;
+        return errors.finish(printer.matched());
     }
+
+    // otherwise each file is searched into its own buffer, and the buffers are
+    // printed in the same order as the files were found; what was found before
+    // an error is still printed, then the error, as when searching one by one
+    parallel::for_each_in_order(
+        &files,
+        jobs,
+        |file| {
+            let mut buffer = Printer::new(Vec::new(), &config, show_path, color);
+            let searched = search_file(file, &matcher, &config, &mut buffer);
+            (buffer, searched)
+        },
+        |(buffer, searched)| {
+            printer.append(buffer)?;
+            searched.or_else(|e| errors.report(e))
+        },
+    )?;
+    printer.summary()?;
+    errors.finish(printer.matched())
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.filename)?;
+// The files that could not be searched, so run can fail once it is done with
+// the rest
+#[derive(Default)]
+struct Errors {
+    count: usize,
+}
 
-    let results = match config.case_sensitive {
-        true => search(&config.query, &contents),
-        false => search_case_insensitive(&config.query, &contents),
+impl Errors {
+    // Print an error about one file to stderr and carry on; any other error,
+    // e.g. with writing the output, is handed back to stop the search
+    fn report(&mut self, e: Error) -> Result<(), Error> {
+        if !e.is_about_a_file() {
+            return Err(e);
+        }
+        eprintln!("Application error: {}", e.describe());
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(self, matched: bool) -> Result<bool, Error> {
+        match self.count {
+            0 => Ok(matched),
+            n => Err(Error::Skipped(n)),
+        }
+    }
+}
+
+// Search one file (or - for standard input) and print the results
+fn search_file<W: Write>(
+    file: &Path,
+    matcher: &dyn Matcher,
+    config: &Config,
+    printer: &mut Printer<W>,
+) -> Result<(), Error> {
+    // errors from reading are about the file, the rest are about the output
+    let read_error = |e| Error::io(file, e);
+
+    // each file in an archive is searched as if it were on its own, and
+    // reported as archive.zip!path/in/archive
+    if let Some(kind) = archive::kind(file) {
+        if config.in_place {
+            let message = "cannot replace in place in an archive";
+            return Err(read_error(io::Error::other(message)));
+        }
+        return archive::for_each_file(file, kind, |inner, reader| {
+            let name = format!("{}!{}", file.display(), inner);
+            search_input(
+                &name,
+                Path::new(&name),
+                reader,
+                None,
+                matcher,
+                config,
+                printer,
+            )
+            .map(|_| ())
+        });
+    }
+
+    // - is standard input, like most command line tools
+    let (name, opened) = match file.to_str() {
+        Some("-") => (String::from("(standard input)"), None),
+        _ => (
+            file.display().to_string(),
+            Some(File::open(file).map_err(read_error)?),
+        ),
+    };
+    let map = match &opened {
+        Some(opened) if use_mmap(opened, config).map_err(read_error)? => {
+            // the one thing memmap2 cannot promise is that nobody truncates the
+            // file while we read it, which would kill us with SIGBUS; grep and
+            // ripgrep take the same risk, and --no-mmap avoids it
+            Some(unsafe { Mmap::map(opened) }.map_err(read_error)?)
+        }
+        _ => None,
+    };
+    let reader: Box<dyn BufRead> = match (opened, &map) {
+        (_, Some(map)) => Box::new(&map[..]),
+        (Some(opened), None) if config.follow => Box::new(BufReader::new(
+            Follow::new(file, opened).map_err(read_error)?,
+        )),
+        (Some(opened), None) => Box::new(BufReader::new(opened)),
+        (None, None) => Box::new(io::stdin().lock()),
     };
+    let input = search_input(
+        &name,
+        file,
+        reader,
+        map.as_deref(),
+        matcher,
+        config,
+        printer,
+    )?;
 
-    for line in results {
-        println!("{}", line);
+    // the preview has been printed, now change the file itself, in exactly
+    // the places it showed; binary files are left alone, a replacement could
+    // easily break them, and so are files with nothing to replace
+    if let (Some(with), true) = (&config.replace, config.in_place) {
+        if file != Path::new("-") && !input.binary && !input.spans.is_empty() {
+            replace_in_file(file, &input.spans, with).map_err(read_error)?;
+        }
     }
     Ok(())
 }
 
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let mut results = Vec::new();
-    for line in contents.lines() {
-        if line.contains(query) {
-            results.push(line);
+// What search_input found out about the input, for --in-place
+struct Input {
+    binary: bool,
+    // the matches in each line, only kept for --in-place
+    spans: BTreeMap<usize, Vec<Span>>,
+}
+
+// Search what was read from a file, or from a file in an archive. `map` is the
+// whole file when it is memory-mapped, and then `reader` reads from it.
+fn search_input<W: Write>(
+    name: &str,
+    path: &Path,
+    mut reader: Box<dyn BufRead + '_>,
+    map: Option<&[u8]>,
+    matcher: &dyn Matcher,
+    config: &Config,
+    printer: &mut Printer<W>,
+) -> Result<Input, Error> {
+    let read_error = |e| Error::io(path, e);
+
+    // peek at the start of the file without consuming it
+    let compressed = decompress::detect(reader.fill_buf().map_err(read_error)?).is_some();
+    let mut input = Input {
+        binary: false,
+        spans: BTreeMap::new(),
+    };
+    // -z only searches compressed input, so in a directory of logs the ones
+    // not rotated yet are passed over, like binary files are
+    if config.decompress && !compressed {
+        return Ok(input);
+    }
+    let mut reader = decompress::decompressed(reader).map_err(read_error)?;
+    let decoded = decode::needs_decoding(reader.fill_buf().map_err(read_error)?, config.encoding);
+    let mut reader = decode::decoded(reader, config.encoding).map_err(read_error)?;
+    // UTF-16 has lots of NUL bytes, so this only makes sense after decoding
+    let binary = config.binary != BinaryMode::Text
+        && walk::is_binary(reader.fill_buf().map_err(read_error)?);
+    input.binary = binary;
+    if binary && config.binary == BinaryMode::Skip {
+        return Ok(input);
+    }
+    // the text we would replace in is not what is on disk, so say so before
+    // showing a preview of replacements that cannot be made
+    if config.in_place && !binary && (compressed || decoded) {
+        let kind = if compressed {
+            "compressed"
+        } else {
+            "re-encoded"
+        };
+        let message = format!("cannot replace in place in a {} file", kind);
+        return Err(read_error(io::Error::other(message)));
+    }
+
+    printer.begin(name, binary)?;
+    let searcher = Searcher::with_config(matcher, config);
+    let search = |sink: &mut dyn Sink| match map {
+        // the mapped bytes are the text itself, so search them where they are
+        Some(map) if !compressed && !decoded => {
+            searcher.search_slice(map, sink);
+            Ok(())
         }
+        _ => searcher.search_reader(reader, sink).map_err(read_error),
+    };
+    if config.in_place {
+        let mut recorder = Recorder::new(&mut *printer);
+        search(&mut recorder)?;
+        input.spans = recorder.spans;
+    } else {
+        search(printer)?;
+    }
+    // the printer cannot return its error through the search, so it kept it
+    match printer.take_error() {
+        Some(e) => Err(e.into()),
+        None => Ok(input),
     }
-    results
 }
 
-pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    let mut results = Vec::new();
+// Mapping a file costs more up front than reading it, and only pays off when
+// there is a lot to read, so by default only large files are mapped
+const MMAP_MIN_SIZE: u64 = 16 * 1024 * 1024;
 
-    for line in contents.lines() {
-        if line.to_lowercase().contains(&query) {
-            results.push(line);
+fn use_mmap(file: &File, config: &Config) -> io::Result<bool> {
+    // a map is as big as the file was, so it would not see the file grow
+    if config.mmap == Some(false) || config.follow {
+        return Ok(false);
+    }
+    // not pipes and devices, and an empty file cannot be mapped
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.len() == 0 {
+        return Ok(false);
+    }
+    Ok(config.mmap == Some(true) || metadata.len() >= MMAP_MIN_SIZE)
+}
+
+// A matching line and where it was found. With multiline (-U) it can be
+// several lines, separated by \n in `line`.
+#[derive(Debug, PartialEq)]
+pub struct Match {
+    // 1-based, like editors count lines; the first line with multiline
+    pub line_number: usize,
+    // of the start of the line, from the start of the input
+    pub byte_offset: usize,
+    pub line: String,
+    // the matches within the line, empty for inverted matches
+    pub spans: Vec<Span>,
+}
+impl Match {
+    // the numbers of the first and last line
+    pub fn line_range(&self) -> RangeInclusive<usize> {
+        self.line_number..=self.line_number + self.line.matches('\n').count()
+    }
+
+    // One Match for each line, with the parts of the spans that are on it
+    pub fn split_lines(&self) -> Vec<Match> {
+        let mut results = Vec::new();
+        let mut start = 0;
+        for (i, text) in self.line.split('\n').enumerate() {
+            let line = text.strip_suffix('\r').unwrap_or(text);
+            let end = start + line.len();
+            let spans = self
+                .spans
+                .iter()
+                .filter(|s| match s.start == s.end {
+                    true => start <= s.start && s.start <= end,
+                    false => s.start < end && start < s.end,
+                })
+                .map(|s| Span {
+                    start: s.start.max(start) - start,
+                    end: s.end.min(end) - start,
+                    pattern: s.pattern,
+                })
+                .collect();
+            results.push(Match {
+                line_number: self.line_number + i,
+                byte_offset: self.byte_offset + start,
+                line: line.to_string(),
+                spans,
+            });
+            start += text.len() + 1;
         }
+        results
     }
+}
 
+pub fn search(query: &str, contents: &str) -> Vec<Match> {
+    search_with(&QueryMatcher::Literal(query.to_string()), contents, false)
+}
+
+pub fn search_case_insensitive(query: &str, contents: &str) -> Vec<Match> {
+    search_with(&QueryMatcher::case_insensitive(query), contents, false)
+}
+
+// the regex is compiled once by the caller and reused for every line
+pub fn search_regex(re: &Regex, contents: &str) -> Vec<Match> {
+    search_with(&QueryMatcher::Regex(re.clone()), contents, false)
+}
+
+// -v flips the match, so inverted results are just the other lines
+pub fn search_with(matcher: &dyn Matcher, contents: &str, invert: bool) -> Vec<Match> {
+    search_with_context(matcher, contents, invert, 0, 0)
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Entry::Match(result) => Some(result),
+            _ => None,
+        })
+        .collect()
+}
+
+// The results of a search with context lines, as a Sink collects them
+#[derive(Debug, PartialEq)]
+pub enum Entry {
+    Match(Match),
+    // a line near a match, its spans are always empty
+    Context(Match),
+    // the gap between two groups of lines that are not next to each other
+    Break,
+}
+
+pub fn search_with_context(
+    matcher: &dyn Matcher,
+    contents: &str,
+    invert: bool,
+    before: usize,
+    after: usize,
+) -> Vec<Entry> {
+    let mut searcher = Searcher::new(matcher);
+    searcher.invert = invert;
+    searcher.before_context = before;
+    searcher.after_context = after;
+    let mut results = Vec::new();
+    searcher.search_str(contents, &mut results);
     results
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::config;
+
+    fn lines(results: Vec<Match>) -> Vec<String> {
+        results.into_iter().map(|m| m.line).collect()
+    }
+
+    fn ranges(result: &Match) -> Vec<(usize, usize)> {
+        result.spans.iter().map(|s| (s.start, s.end)).collect()
+    }
 
     #[test]
     fn one_result() {
@@ -73,7 +438,10 @@ Rust:
 safe, fast, productive.
 Pick three.";
 
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+        assert_eq!(
+            vec!["safe, fast, productive."],
+            lines(search(query, contents))
+        );
     }
 
     #[test]
@@ -85,7 +453,10 @@ safe, fast, productive.
 Pick three.
 Duct tape.";
 
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+        assert_eq!(
+            vec!["safe, fast, productive."],
+            lines(search(query, contents))
+        );
     }
 
     #[test]
@@ -99,7 +470,196 @@ Trust me.";
 
         assert_eq!(
             vec!["Rust:", "Trust me."],
-            search_case_insensitive(query, contents)
+            lines(search_case_insensitive(query, contents))
         );
     }
+
+    #[test]
+    fn regex() {
+        let re = build_regex(r"\d{2}:\d{2}", true).unwrap();
+        let contents = "\
+12:30 request started
+no time here
+13:05 request done";
+
+        assert_eq!(
+            vec!["12:30 request started", "13:05 request done"],
+            lines(search_regex(&re, contents))
+        );
+    }
+
+    #[test]
+    fn regex_case_insensitive() {
+        let re = build_regex("^r.st", false).unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Trust me.";
+
+        assert_eq!(vec!["Rust:"], lines(search_regex(&re, contents)));
+    }
+
+    #[test]
+    fn regex_compile_error() {
+        assert!(build_regex("(unclosed", true).is_err());
+    }
+
+    #[test]
+    fn positions() {
+        let contents = "Rust:\r\nsafe, fast, productive.\nPick three.";
+
+        assert_eq!(
+            vec![Match {
+                line_number: 2,
+                byte_offset: 7,
+                line: String::from("safe, fast, productive."),
+                spans: vec![Span {
+                    start: 15,
+                    end: 19,
+                    pattern: 0
+                }],
+            }],
+            search("duct", contents)
+        );
+    }
+
+    #[test]
+    fn all_spans_in_line() {
+        let results = search("a", "banana");
+        assert_eq!(vec![(1, 2), (3, 4), (5, 6)], ranges(&results[0]));
+    }
+
+    #[test]
+    fn case_insensitive_spans_follow_the_original_line() {
+        // the Kelvin sign is 3 bytes but lowercases to a 1 byte k
+        let results = search_case_insensitive("ok", "\u{212A}O\u{212A} ok");
+        assert_eq!(vec![(3, 7), (8, 10)], ranges(&results[0]));
+    }
+
+    #[test]
+    fn inverted() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.";
+
+        let results = search_with(&QueryMatcher::Literal(String::from("duct")), contents, true);
+        assert_eq!(vec!["Rust:", "Pick three."], lines(results));
+    }
+
+    #[test]
+    fn reader_with_invalid_utf8() {
+        let contents: &[u8] = b"caf\xe9 latte\nplain tea\n";
+        let matcher = QueryMatcher::Literal(String::from("latte"));
+        let mut results = Vec::new();
+        Searcher::new(&matcher)
+            .search_reader(contents, &mut results)
+            .unwrap();
+
+        assert_eq!(1, results.len());
+        assert_eq!(
+            Entry::Match(Match {
+                line_number: 1,
+                byte_offset: 0,
+                line: String::from("caf\u{FFFD} latte"),
+                spans: vec![Span {
+                    start: 7,
+                    end: 12,
+                    pattern: 0
+                }],
+            }),
+            results[0]
+        );
+    }
+
+    fn numbers(entries: Vec<Entry>) -> Vec<String> {
+        entries
+            .into_iter()
+            .map(|entry| match entry {
+                Entry::Match(m) => format!("{}:", m.line_number),
+                Entry::Context(m) => format!("{}-", m.line_number),
+                Entry::Break => String::from("--"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn context_lines() {
+        let contents = "1\n2\nmatch\n4\n5\n6\n7\nmatch\n9";
+        let matcher = QueryMatcher::Literal(String::from("match"));
+
+        assert_eq!(
+            vec!["2-", "3:", "4-", "--", "7-", "8:", "9-"],
+            numbers(search_with_context(&matcher, contents, false, 1, 1))
+        );
+        assert_eq!(
+            vec!["1-", "2-", "3:", "--", "6-", "7-", "8:"],
+            numbers(search_with_context(&matcher, contents, false, 2, 0))
+        );
+    }
+
+    #[test]
+    fn overlapping_context_is_merged() {
+        let contents = "1\nmatch\n3\n4\nmatch\n6\nmatch";
+        let matcher = QueryMatcher::Literal(String::from("match"));
+
+        assert_eq!(
+            vec!["1-", "2:", "3-", "4-", "5:", "6-", "7:"],
+            numbers(search_with_context(&matcher, contents, false, 1, 2))
+        );
+        // no separator when the groups just touch
+        assert_eq!(
+            vec!["2:", "3-", "4-", "5:", "6-", "7:"],
+            numbers(search_with_context(&matcher, contents, false, 0, 2))
+        );
+    }
+
+    #[test]
+    fn no_separator_without_context() {
+        let contents = "match\n2\nmatch";
+        let matcher = QueryMatcher::Literal(String::from("match"));
+
+        assert_eq!(
+            vec!["1:", "3:"],
+            numbers(search_with_context(&matcher, contents, false, 0, 0))
+        );
+    }
+
+    #[test]
+    fn errors_about_one_file_are_counted() {
+        let mut errors = Errors::default();
+        let missing = || io::Error::from(io::ErrorKind::NotFound);
+        errors.report(Error::io("nosuch", missing())).unwrap();
+        errors.report(Error::io("gone", missing())).unwrap();
+        // but the output failing stops everything
+        assert!(errors.report(Error::from(missing())).is_err());
+        assert!(matches!(errors.finish(true), Err(Error::Skipped(2))));
+        assert!(matches!(Errors::default().finish(false), Ok(false)));
+    }
+
+    #[test]
+    fn decompress_skips_plain_input() {
+        let config = config(&["-z", "ERROR"]);
+        let matcher = QueryMatcher::new(&config).unwrap();
+        let search = |bytes: Vec<u8>| {
+            let mut printer = Printer::new(Vec::new(), &config, true, false);
+            let reader = Box::new(io::Cursor::new(bytes));
+            search_input(
+                "app.log",
+                Path::new("app.log"),
+                reader,
+                None,
+                &matcher,
+                &config,
+                &mut printer,
+            )
+            .unwrap();
+            String::from_utf8(printer.into_inner()).unwrap()
+        };
+        let log = b"INFO started\nERROR disk full\n".to_vec();
+        assert_eq!("", search(log.clone()));
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&log).unwrap();
+        assert_eq!("app.log:ERROR disk full\n", search(gz.finish().unwrap()));
+    }
 }
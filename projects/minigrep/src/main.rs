@@ -1,20 +1,28 @@
 use std::env;
 use std::process;
 
-use minigrep::Config;
+use minigrep::{Config, Error};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    let config = Config::new(&args).unwrap_or_else(|err| {
+    let config = Config::new(env::args()).unwrap_or_else(|err| {
         // print to stderr
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
+        eprintln!("Problem parsing arguments: {}", err.describe());
+        process::exit(2);
     });
 
-    if let Err(e) = minigrep::run(config) {
-        // print to stderr
-        eprintln!("Application error: {}", e);
-        process::exit(1);
+    // the same exit status as grep: 0 when something matched, 1 when
+    // nothing did and 2 when something went wrong
+    match minigrep::run(config) {
+        Ok(true) => process::exit(0),
+        Ok(false) => process::exit(1),
+        // e.g. piped into head, which has seen enough
+        Err(e) if e.is_broken_pipe() => process::exit(0),
+        // the files were already reported one by one
+        Err(Error::Skipped(_)) => process::exit(2),
+        Err(e) => {
+            // print to stderr
+            eprintln!("Application error: {}", e.describe());
+            process::exit(2);
+        }
     }
 }
@@ -0,0 +1,759 @@
+use std::cmp::Reverse;
+use std::iter;
+
+use aho_corasick::{AhoCorasick, MatchKind};
+use caseless::Caseless;
+use regex::{Regex, RegexBuilder};
+use regex_automata::meta;
+use regex_automata::util::syntax;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::fields::{self, FieldFilter};
+use crate::{Config, Error};
+
+// Where a pattern matched in a line
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Span {
+    // byte range within the line
+    pub start: usize,
+    pub end: usize,
+    // index of the pattern that matched, in the order they were given
+    pub pattern: usize,
+}
+
+// Finds the matches in a line. The Searcher only needs this, so a tool
+// using minigrep as a library can plug in its own kind of query.
+pub trait Matcher {
+    // every non-overlapping match in the line, from left to right
+    fn find_all(&self, line: &str) -> Vec<Span>;
+
+    // Whether the line matches, which the Searcher asks first. Worth
+    // overriding when there is a faster way to just say yes or no, or when a
+    // line can match with nothing in it to highlight, like with --json-field
+    // and no query.
+    fn is_match(&self, line: &str) -> bool {
+        !self.find_all(line).is_empty()
+    }
+}
+
+// The query from the command line, compiled once up front and then reused
+// for every line
+pub enum QueryMatcher {
+    Literal(String),
+    // the query after Unicode case folding, see QueryMatcher::case_insensitive
+    CaseInsensitive(Vec<char>),
+    Regex(Regex),
+    // many literal patterns, all searched for in one pass over the line
+    Patterns(AhoCorasick),
+    // many literal patterns with -i, folded like CaseInsensitive and all
+    // searched for in one pass over the folded line; the index of an empty
+    // one, if any, which matches every line
+    CaseInsensitivePatterns(AhoCorasick, Option<usize>),
+    // many regexes compiled into one, which says which of them matched
+    RegexPatterns(meta::Regex),
+    // only the matches that are whole words (-w) or whole lines (-x); a word
+    // also has the query built to match whole lines, to try the words a
+    // rejected match started
+    WholeWord(Box<QueryMatcher>, Box<QueryMatcher>),
+    WholeLine(Box<QueryMatcher>),
+    // only the lines that are JSON with the --json-field values, and match
+    // the query too when there is one
+    Fields {
+        filters: Vec<FieldFilter>,
+        case_sensitive: bool,
+        query: Option<Box<QueryMatcher>>,
+    },
+}
+impl QueryMatcher {
+    pub fn new(config: &Config) -> Result<QueryMatcher, Error> {
+        // with --json-field the query is optional
+        if config.patterns.is_empty() && !config.json_fields.is_empty() {
+            return Ok(QueryMatcher::Fields {
+                filters: config.json_fields.clone(),
+                case_sensitive: config.case_sensitive,
+                query: None,
+            });
+        }
+        let matcher = QueryMatcher::build(config, config.line_regexp)?;
+        let matcher = match (config.line_regexp, config.word_regexp) {
+            (true, _) => QueryMatcher::WholeLine(Box::new(matcher)),
+            (false, true) => QueryMatcher::WholeWord(
+                Box::new(matcher),
+                Box::new(QueryMatcher::build(config, true)?),
+            ),
+            (false, false) => matcher,
+        };
+        Ok(match config.json_fields.is_empty() {
+            true => matcher,
+            false => QueryMatcher::Fields {
+                filters: config.json_fields.clone(),
+                case_sensitive: config.case_sensitive,
+                query: Some(Box::new(matcher)),
+            },
+        })
+    }
+
+    fn build(config: &Config, whole_line: bool) -> Result<QueryMatcher, Error> {
+        // a regex finds the leftmost match, which might be shorter than the
+        // whole line even when the line matches, so anchor it for -x
+        let anchor = |pattern: &str| match whole_line {
+            true => format!("^(?:{})$", pattern),
+            false => pattern.to_string(),
+        };
+
+        // an empty -f file, so nothing can match
+        if config.patterns.is_empty() {
+            let ac = AhoCorasick::new(&config.patterns).map_err(|e| Error::pattern("", e))?;
+            return Ok(QueryMatcher::Patterns(ac));
+        }
+        if let [pattern] = config.patterns.as_slice() {
+            return Ok(match (config.regex, config.case_sensitive) {
+                (true, _) => QueryMatcher::Regex(
+                    compile(&anchor(pattern), config).map_err(|e| Error::pattern(pattern, e))?,
+                ),
+                (false, true) => QueryMatcher::Literal(pattern.clone()),
+                (false, false) => QueryMatcher::case_insensitive(pattern),
+            });
+        }
+
+        // the regex crate only knows simple case folding, which would not
+        // find "Straße" for STRASSE, so each pattern is folded on its own
+        if !config.regex && !config.case_sensitive {
+            let folded: Vec<String> = config
+                .patterns
+                .iter()
+                .map(|p| p.chars().default_case_fold().collect())
+                .collect();
+            // every match, overlapping too, since the longest one might end
+            // halfway through a character of the line
+            let ac = AhoCorasick::builder()
+                .match_kind(MatchKind::Standard)
+                .build(&folded)
+                .map_err(|e| Error::pattern(&config.patterns.join("\n"), e))?;
+            let empty = folded.iter().position(String::is_empty);
+            return Ok(QueryMatcher::CaseInsensitivePatterns(ac, empty));
+        }
+        if !config.regex {
+            // leftmost-longest, so of "ab" and "abc" the longer one is reported
+            let ac = AhoCorasick::builder()
+                .match_kind(MatchKind::LeftmostLongest)
+                .build(&config.patterns)
+                // only when there are too many patterns to fit the limits
+                .map_err(|e| Error::pattern(&config.patterns.join("\n"), e))?;
+            return Ok(QueryMatcher::Patterns(ac));
+        }
+
+        // like an alternation of them all, each pattern with its own groups
+        let patterns: Vec<String> = config.patterns.iter().map(|p| anchor(p)).collect();
+        let re = meta::Regex::builder()
+            .syntax(
+                syntax::Config::new()
+                    .case_insensitive(!config.case_sensitive)
+                    .multi_line(config.multiline),
+            )
+            .build_many(&patterns)
+            .map_err(|e| {
+                // blame the query that broke it, when it is one of them
+                let pattern = match e.pattern() {
+                    Some(id) => config.patterns[id.as_usize()].clone(),
+                    None => config.patterns.join("\n"),
+                };
+                match e.syntax_error() {
+                    // worded the same as for a single --regex
+                    Some(syntax) => Error::pattern(&pattern, syntax.clone()),
+                    None => Error::pattern(&pattern, e),
+                }
+            })?;
+        Ok(QueryMatcher::RegexPatterns(re))
+    }
+
+    // The leftmost match that starts at `start` or later. Unlike slicing the
+    // line, a regex still sees what comes before, e.g. for ^ or \b.
+    fn find_at(&self, line: &str, start: usize) -> Option<Span> {
+        let span = |start, end| Span {
+            start,
+            end,
+            pattern: 0,
+        };
+        match self {
+            QueryMatcher::Literal(query) => line[start..]
+                .find(query.as_str())
+                .map(|i| span(start + i, start + i + query.len())),
+            QueryMatcher::CaseInsensitive(query) => find_folded_at(query, line, start),
+            QueryMatcher::CaseInsensitivePatterns(_, Some(pattern)) => Some(Span {
+                start,
+                end: start,
+                pattern: *pattern,
+            }),
+            QueryMatcher::CaseInsensitivePatterns(ac, None) => {
+                folded_matches(ac, line, start).into_iter().next()
+            }
+            QueryMatcher::Regex(re) => re.find_at(line, start).map(|m| span(m.start(), m.end())),
+            QueryMatcher::Patterns(ac) => ac
+                .find(aho_corasick::Input::new(line).range(start..))
+                .map(|m| Span {
+                    start: m.start(),
+                    end: m.end(),
+                    pattern: m.pattern().as_usize(),
+                }),
+            QueryMatcher::RegexPatterns(re) => re
+                .find(regex_automata::Input::new(line).range(start..))
+                .map(pattern_span),
+            QueryMatcher::WholeWord(..)
+            | QueryMatcher::WholeLine(_)
+            | QueryMatcher::Fields { .. } => {
+                self.find_all(line).into_iter().find(|s| s.start >= start)
+            }
+        }
+    }
+
+    // Full Unicode case folding rather than to_lowercase, so "STRASSE" matches
+    // "Straße" and "ΣΟΦΟΣ" matches "σοφος" with its final sigma.
+    pub fn case_insensitive(query: &str) -> QueryMatcher {
+        QueryMatcher::CaseInsensitive(query.chars().default_case_fold().collect())
+    }
+}
+// How many word boundaries before and after the end of a match that is not a
+// whole word -w tries, for a word that ends there instead
+const WORD_ENDS_TRIED: usize = 4;
+
+impl Matcher for QueryMatcher {
+    fn is_match(&self, line: &str) -> bool {
+        match self {
+            QueryMatcher::Literal(query) => line.contains(query.as_str()),
+            QueryMatcher::CaseInsensitive(query) => {
+                query.is_empty()
+                    || line
+                        .char_indices()
+                        .any(|(i, _)| match_folded_at(query, line, i).is_some())
+            }
+            QueryMatcher::Regex(re) => re.is_match(line),
+            QueryMatcher::RegexPatterns(re) => re.is_match(line),
+            QueryMatcher::Patterns(ac) => ac.is_match(line),
+            QueryMatcher::CaseInsensitivePatterns(..)
+            | QueryMatcher::WholeWord(..)
+            | QueryMatcher::WholeLine(_) => !self.find_all(line).is_empty(),
+            // the query first, it is much cheaper than parsing the line
+            QueryMatcher::Fields {
+                filters,
+                case_sensitive,
+                query,
+            } => {
+                query.as_ref().is_none_or(|query| query.is_match(line))
+                    && fields::all_match(filters, line, *case_sensitive)
+            }
+        }
+    }
+
+    fn find_all(&self, line: &str) -> Vec<Span> {
+        let span = |start, end| Span {
+            start,
+            end,
+            pattern: 0,
+        };
+        match self {
+            QueryMatcher::Literal(query) if query.is_empty() => vec![span(0, 0)],
+            QueryMatcher::Literal(query) => line
+                .match_indices(query.as_str())
+                .map(|(i, m)| span(i, i + m.len()))
+                .collect(),
+            QueryMatcher::CaseInsensitive(query) => find_all_folded(query, line),
+            QueryMatcher::CaseInsensitivePatterns(_, Some(pattern)) => vec![Span {
+                start: 0,
+                end: 0,
+                pattern: *pattern,
+            }],
+            QueryMatcher::CaseInsensitivePatterns(ac, None) => {
+                // the leftmost-longest of those that do not overlap
+                let mut last = 0;
+                folded_matches(ac, line, 0)
+                    .into_iter()
+                    .filter(|s| {
+                        let keep = s.start >= last;
+                        if keep {
+                            last = s.end;
+                        }
+                        keep
+                    })
+                    .collect()
+            }
+            QueryMatcher::Regex(re) => re
+                .find_iter(line)
+                .map(|m| span(m.start(), m.end()))
+                .collect(),
+            QueryMatcher::Patterns(ac) => ac
+                .find_iter(line)
+                .map(|m| Span {
+                    start: m.start(),
+                    end: m.end(),
+                    pattern: m.pattern().as_usize(),
+                })
+                .collect(),
+            QueryMatcher::RegexPatterns(re) => re.find_iter(line).map(pattern_span).collect(),
+            QueryMatcher::WholeWord(inner, whole) => {
+                if !inner.is_match(line) {
+                    return Vec::new();
+                }
+                // the word boundaries of UAX #29, so "id" is not a word in "width"
+                // or "valid_id", but is in "user-id" and next to any script's punctuation
+                let bounds: Vec<usize> = line
+                    .split_word_bound_indices()
+                    .map(|(i, _)| i)
+                    .chain(iter::once(line.len()))
+                    .collect();
+                let is_bound = |i| bounds.binary_search(&i).is_ok();
+
+                // all the matches of the folded patterns are there at once,
+                // the shorter ones at the same place too
+                if let QueryMatcher::CaseInsensitivePatterns(ac, None) = inner.as_ref() {
+                    let mut last = 0;
+                    return folded_matches(ac, line, 0)
+                        .into_iter()
+                        .filter(|s| {
+                            let keep = s.start >= last && is_bound(s.start) && is_bound(s.end);
+                            if keep {
+                                last = s.end;
+                            }
+                            keep
+                        })
+                        .collect();
+                }
+
+                let mut spans = Vec::new();
+                let mut start = 0;
+                while let Some(found) = inner.find_at(line, start) {
+                    let word = match bounds.binary_search(&found.start) {
+                        Ok(_) if is_bound(found.end) => Some(found),
+                        // another match from the same place may still end on a
+                        // boundary, like identity for --regex 'id|identity'; only
+                        // the few around the end of this one are tried, the
+                        // longest first, so a long line is not searched over and
+                        // over
+                        Ok(first) => {
+                            let after = bounds.partition_point(|&b| b <= found.end);
+                            let from = after.saturating_sub(WORD_ENDS_TRIED).max(first + 1);
+                            let to = (after + WORD_ENDS_TRIED).min(bounds.len());
+                            bounds[from..to].iter().rev().find_map(|&end| {
+                                let word = &line[found.start..end];
+                                let exact = whole
+                                    .find_at(word, 0)
+                                    .filter(|s| s.start == 0 && s.end == word.len())?;
+                                Some(Span {
+                                    start: found.start,
+                                    end,
+                                    pattern: exact.pattern,
+                                })
+                            })
+                        }
+                        Err(_) => None,
+                    };
+                    match word {
+                        // an empty match says the line matches, there is
+                        // nothing more to highlight
+                        Some(word) if word.start == word.end => {
+                            spans.push(word);
+                            break;
+                        }
+                        Some(word) => {
+                            spans.push(word);
+                            start = word.end;
+                        }
+                        // otherwise a word may start inside the match, but only
+                        // on a boundary, so go on from the next one
+                        None => match bounds.get(bounds.partition_point(|&b| b <= found.start)) {
+                            Some(&next) => start = next,
+                            None => break,
+                        },
+                    }
+                }
+                spans
+            }
+            QueryMatcher::WholeLine(inner) => inner
+                .find_all(line)
+                .into_iter()
+                .filter(|s| s.start == 0 && s.end == line.len())
+                .collect(),
+            QueryMatcher::Fields {
+                filters,
+                case_sensitive,
+                query,
+            } => {
+                // without a query there is nothing to highlight, even in the
+                // lines that match
+                let spans = match query {
+                    Some(query) => query.find_all(line),
+                    None => return Vec::new(),
+                };
+                match !spans.is_empty() && fields::all_match(filters, line, *case_sensitive) {
+                    true => spans,
+                    false => Vec::new(),
+                }
+            }
+        }
+    }
+}
+
+// Compare the line against the folded query starting at byte `start`, folding
+// one character at a time so nothing is allocated. A match has to cover whole
+// characters of the line, so "s" does not match half of "ß" (which folds to "ss").
+// Returns the end of the match.
+fn match_folded_at(query: &[char], line: &str, start: usize) -> Option<usize> {
+    let mut query = query.iter();
+    for (i, c) in line[start..].char_indices() {
+        for folded in iter::once(c).default_case_fold() {
+            match query.next() {
+                Some(&q) if q == folded => {}
+                _ => return None,
+            }
+        }
+        if query.as_slice().is_empty() {
+            return Some(start + i + c.len_utf8());
+        }
+    }
+    None
+}
+
+fn find_all_folded(query: &[char], line: &str) -> Vec<Span> {
+    if query.is_empty() {
+        return vec![Span {
+            start: 0,
+            end: 0,
+            pattern: 0,
+        }];
+    }
+    let mut results = Vec::new();
+    let mut start = 0;
+    while let Some(found) = find_folded_at(query, line, start) {
+        results.push(found);
+        start = found.end;
+    }
+    results
+}
+
+// the first match of the folded query at `start` or later
+fn find_folded_at(query: &[char], line: &str, start: usize) -> Option<Span> {
+    if query.is_empty() {
+        return Some(Span {
+            start,
+            end: start,
+            pattern: 0,
+        });
+    }
+    line[start..].char_indices().find_map(|(i, _)| {
+        let end = match_folded_at(query, line, start + i)?;
+        Some(Span {
+            start: start + i,
+            end,
+            pattern: 0,
+        })
+    })
+}
+
+// Every match of the folded patterns from `start` on that covers whole
+// characters of the line, leftmost-longest first, and of two just as long the
+// pattern given first. The line is folded once and searched in one pass, then
+// the matches are mapped back to where they are in the line.
+fn folded_matches(ac: &AhoCorasick, line: &str, start: usize) -> Vec<Span> {
+    let mut folded = String::with_capacity(line.len() - start);
+    // for each byte of the folded line, where in the line the character starts
+    // whose folding starts there; None inside a folding
+    let mut offsets = Vec::with_capacity(line.len() - start + 1);
+    for (i, c) in line[start..].char_indices() {
+        offsets.push(Some(start + i));
+        folded.extend(iter::once(c).default_case_fold());
+        offsets.resize(folded.len(), None);
+    }
+    offsets.push(Some(line.len()));
+
+    let mut matches: Vec<Span> = ac
+        .find_overlapping_iter(&folded)
+        .filter_map(|m| {
+            Some(Span {
+                start: offsets[m.start()]?,
+                end: offsets[m.end()]?,
+                pattern: m.pattern().as_usize(),
+            })
+        })
+        .collect();
+    matches.sort_unstable_by_key(|s| (s.start, Reverse(s.end), s.pattern));
+    matches
+}
+
+fn pattern_span(m: regex_automata::Match) -> Span {
+    Span {
+        start: m.start(),
+        end: m.end(),
+        pattern: m.pattern().as_usize(),
+    }
+}
+
+// With multiline the regex sees the whole file, and ^ and $ should still be
+// the start and end of a line rather than of the file
+fn compile(pattern: &str, config: &Config) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(!config.case_sensitive)
+        .multi_line(config.multiline)
+        .build()
+}
+
+pub fn build_regex(pattern: &str, case_sensitive: bool) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    use crate::testing::config;
+
+    fn spans(query: &str, line: &str) -> Vec<(usize, usize)> {
+        spans_of(&QueryMatcher::case_insensitive(query), line)
+            .into_iter()
+            .map(|(start, end, _)| (start, end))
+            .collect()
+    }
+
+    fn spans_of(matcher: &QueryMatcher, line: &str) -> Vec<(usize, usize, usize)> {
+        matcher
+            .find_all(line)
+            .into_iter()
+            .map(|s| (s.start, s.end, s.pattern))
+            .collect()
+    }
+
+    #[test]
+    fn json_fields() {
+        let error = r#"{"level": "error", "msg": "db timeout"}"#;
+        let info = r#"{"msg": "timeout raised", "level": "info"}"#;
+        let matcher = QueryMatcher::new(&config(&["--json-field", "level=error"])).unwrap();
+        assert!(matcher.is_match(error));
+        assert!(spans_of(&matcher, error).is_empty());
+        assert!(!matcher.is_match(info));
+        assert!(!matcher.is_match("level=error"));
+
+        // and the query, whose matches are the ones highlighted
+        let args = ["--json-field=level=ERROR", "-i", "-e", "timeout"];
+        let matcher = QueryMatcher::new(&config(&args)).unwrap();
+        assert_eq!(vec![(30, 37, 0)], spans_of(&matcher, error));
+        assert!(!matcher.is_match(info));
+    }
+
+    #[test]
+    fn german_sharp_s() {
+        assert_eq!(vec![(0, 7)], spans("STRASSE", "Straße"));
+        assert_eq!(vec![(4, 11)], spans("straße", "Die STRASSE"));
+        // a match cannot end halfway through the ß
+        assert!(spans("stras", "Straße").is_empty());
+    }
+
+    #[test]
+    fn greek_final_sigma() {
+        let line = "ο Οδυσσευς";
+        assert_eq!(vec![(3, line.len())], spans("ΟΔΥΣΣΕΥΣ", line));
+        assert!(QueryMatcher::case_insensitive("σοφος").is_match("ΣΟΦΟΣ"));
+    }
+
+    #[test]
+    fn turkish_dotted_and_dotless_i() {
+        // the default folding maps İ to i followed by a combining dot
+        assert!(QueryMatcher::case_insensitive("i\u{307}stanbul").is_match("İSTANBUL"));
+        // and leaves the dotless ı alone, it is not the same letter as I
+        assert!(!QueryMatcher::case_insensitive("ı").is_match("I"));
+        assert!(QueryMatcher::case_insensitive("ı").is_match("ılık"));
+    }
+
+    #[test]
+    fn scripts_without_case() {
+        // Devanagari has no case, so it just has to be found in the right place
+        let line = "Hello नमस्ते HELLO";
+        assert_eq!(vec![(6, 24)], spans("नमस्ते", line));
+        assert_eq!(vec![(0, 5), (25, 30)], spans("hello", line));
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        assert!(QueryMatcher::case_insensitive("").is_match("anything"));
+    }
+
+    #[test]
+    fn many_literal_patterns() {
+        let matcher =
+            QueryMatcher::new(&config(&["-e", "E1001", "-e", "E42", "-e", "E4200"])).unwrap();
+        assert!(matches!(matcher, QueryMatcher::Patterns(_)));
+        assert_eq!(
+            vec![(7, 12, 0), (19, 24, 2)],
+            spans_of(&matcher, "failed E1001, then E4200")
+        );
+        assert!(!matcher.is_match("all good"));
+    }
+
+    #[test]
+    fn many_case_insensitive_patterns() {
+        let matcher = QueryMatcher::new(&config(&["-i", "-e", "warn", "-e", "a.b"])).unwrap();
+        // the patterns are still literals, so the . is not a wildcard
+        assert_eq!(
+            vec![(0, 4, 0), (9, 12, 1)],
+            spans_of(&matcher, "WARN axb A.B")
+        );
+    }
+
+    #[test]
+    fn many_patterns_fold_case_fully() {
+        let matcher = QueryMatcher::new(&config(&["-i", "-e", "STRASSE", "-e", "zzz"])).unwrap();
+        assert_eq!(vec![(0, 7, 0)], spans_of(&matcher, "Straße"));
+        let matcher = QueryMatcher::new(&config(&[
+            "-i",
+            "-e",
+            "ΟΔΥΣΣΕΥΣ",
+            "-e",
+            "straße",
+            "-e",
+            "i\u{307}stanbul",
+        ]))
+        .unwrap();
+        let line = "ο Οδυσσευς, die STRASSE";
+        assert_eq!(vec![(3, 19, 0), (25, 32, 1)], spans_of(&matcher, line));
+        assert!(matcher.is_match("İSTANBUL"));
+        assert!(!matcher.is_match("Strasbourg"));
+        // of two at the same place the longer one, as without -i
+        let matcher = QueryMatcher::new(&config(&["-i", "-e", "E42", "-e", "e4200"])).unwrap();
+        assert_eq!(vec![(0, 5, 1)], spans_of(&matcher, "E4200"));
+        // the longest may end halfway through the ß, a shorter one does not
+        let matcher = QueryMatcher::new(&config(&["-i", "-e", "ss", "-e", "s"])).unwrap();
+        assert_eq!(vec![(0, 1, 1), (1, 3, 0)], spans_of(&matcher, "sß"));
+    }
+
+    #[test]
+    fn many_regex_patterns() {
+        let matcher =
+            QueryMatcher::new(&config(&["--regex", "-e", r"(\d+)ms", "-e", "[a-f]{4}"])).unwrap();
+        assert_eq!(
+            vec![(0, 4, 1), (8, 13, 0)],
+            spans_of(&matcher, "beef in 250ms")
+        );
+    }
+
+    #[test]
+    fn patterns_with_their_own_groups() {
+        let args = ["--regex", "-e", "(?P<p1>a)", "-e", "c", "-e", "(?P<p0>b)|d"];
+        let matcher = QueryMatcher::new(&config(&args)).unwrap();
+        assert_eq!(vec![(1, 2, 0), (3, 4, 1)], spans_of(&matcher, "-a c"));
+        assert_eq!(vec![(0, 1, 2)], spans_of(&matcher, "b"));
+    }
+
+    #[test]
+    fn bad_pattern_is_named() {
+        let err = QueryMatcher::new(&config(&["--regex", "-e", r"\d+ms", "-e", "(oops"]));
+        match err {
+            Err(Error::Pattern { pattern, .. }) => assert_eq!("(oops", pattern),
+            _ => panic!("expected a pattern error"),
+        }
+    }
+
+    #[test]
+    fn no_patterns() {
+        let mut config = config(&["-i", "-e", "x"]);
+        config.patterns.clear();
+        assert!(!QueryMatcher::new(&config).unwrap().is_match("anything"));
+    }
+
+    fn lines_matching(args: &[&str], lines: &[&str]) -> Vec<String> {
+        let matcher = QueryMatcher::new(&config(args)).unwrap();
+        lines
+            .iter()
+            .filter(|line| matcher.is_match(line))
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn whole_words() {
+        let lines = [
+            "let id = 1;",
+            "width",
+            "valid_id",
+            "user-id",
+            "ID card",
+            "(id)",
+        ];
+        assert_eq!(
+            vec!["let id = 1;", "user-id", "(id)"],
+            lines_matching(&["-w", "id"], &lines)
+        );
+        assert_eq!(
+            vec!["let id = 1;", "user-id", "ID card", "(id)"],
+            lines_matching(&["-wi", "id"], &lines)
+        );
+        assert_eq!(
+            vec!["let id = 1;", "user-id", "(id)"],
+            lines_matching(&["-w", "--regex", "i."], &lines)
+        );
+        // the leftmost match is only id, which is not a word here
+        let matcher = QueryMatcher::new(&config(&["-w", "--regex", "id|identity"])).unwrap();
+        assert_eq!(vec![(0, 8, 0)], spans_of(&matcher, "identity"));
+        let matcher = QueryMatcher::new(&config(&["-w", "-e", "foo-bar", "-e", "foo"])).unwrap();
+        assert_eq!(vec![(0, 3, 1)], spans_of(&matcher, "foo-barx"));
+        // and a word can start inside a match that is not one
+        assert_eq!(
+            vec![(3, 6, 0)],
+            spans_of(
+                &QueryMatcher::new(&config(&["-w", "a-a"])).unwrap(),
+                "xa-a-a"
+            )
+        );
+    }
+
+    #[test]
+    fn whole_words_in_a_long_line() {
+        // every a but the last is not a word, and each of them is only
+        // checked against the few words after it
+        let line = format!("{}a", "ab ".repeat(20_000));
+        let started = Instant::now();
+        for args in [
+            &["-w", "a"][..],
+            &["-w", "--regex", "a|ab c"],
+            &["-w", "-i", "-e", "A", "-e", "x"],
+        ] {
+            let matcher = QueryMatcher::new(&config(args)).unwrap();
+            assert_eq!(vec![(60_000, 60_001, 0)], spans_of(&matcher, &line));
+        }
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn whole_words_in_other_scripts() {
+        let lines = ["Ἀθῆναι καὶ Σπάρτη", "Σπάρτηνδε", "नमस्ते दुनिया"];
+        assert_eq!(
+            vec!["Ἀθῆναι καὶ Σπάρτη"],
+            lines_matching(&["-w", "Σπάρτη"], &lines)
+        );
+        // the virama and vowel signs are part of the word, not boundaries
+        assert_eq!(vec!["नमस्ते दुनिया"], lines_matching(&["-w", "नमस्ते"], &lines));
+        assert!(lines_matching(&["-w", "नमस"], &lines).is_empty());
+    }
+
+    #[test]
+    fn later_whole_word_is_found() {
+        let matcher = QueryMatcher::new(&config(&["-w", "id"])).unwrap();
+        assert_eq!(vec![(7, 9, 0)], spans_of(&matcher, "width, id"));
+    }
+
+    #[test]
+    fn whole_lines() {
+        let lines = ["id", "id ", "ID", "valid"];
+        assert_eq!(vec!["id"], lines_matching(&["-x", "id"], &lines));
+        assert_eq!(vec!["id", "ID"], lines_matching(&["-xi", "id"], &lines));
+        assert_eq!(
+            vec!["id", "valid"],
+            lines_matching(&["-x", "-e", "id", "-e", "valid"], &lines)
+        );
+        // the leftmost match of a|ab in "ab" is just "a", the anchors fix that
+        assert_eq!(
+            vec!["ab"],
+            lines_matching(&["-x", "--regex", "a|ab"], &["ab"])
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+// Run `work` on every item with a pool of `jobs` threads, and hand the results
+// to `output` one at a time in the order of `items`, however the threads finish.
+// Results that arrive early wait in a map until it is their turn.
+//
+// If `output` fails, the threads stop picking up new items and the error is returned.
+pub fn for_each_in_order<T, R, E, W, O>(
+    items: &[T],
+    jobs: usize,
+    work: W,
+    mut output: O,
+) -> Result<(), E>
+where
+    T: Sync,
+    R: Send,
+    W: Fn(&T) -> R + Sync,
+    O: FnMut(R) -> Result<(), E>,
+{
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|s| {
+        for _ in 0..jobs.max(1) {
+            let tx = tx.clone();
+            let (next, stop, work) = (&next, &stop, &work);
+            s.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= items.len() || stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                if tx.send((i, work(&items[i]))).is_err() {
+                    break;
+                }
+            });
+        }
+        // the loop below ends when every thread has dropped its sender
+        drop(tx);
+
+        let mut pending = HashMap::new();
+        let mut wanted = 0;
+        for (i, result) in rx {
+            pending.insert(i, result);
+            while let Some(result) = pending.remove(&wanted) {
+                wanted += 1;
+                if let Err(e) = output(result) {
+                    stop.store(true, Ordering::SeqCst);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn results_come_out_in_order() {
+        let items: Vec<u64> = (0..20).collect();
+        let mut seen = Vec::new();
+
+        // the early items take the longest, so they finish last
+        let result: Result<(), ()> = for_each_in_order(
+            &items,
+            4,
+            |&i| {
+                thread::sleep(Duration::from_millis(20 - i));
+                i * 10
+            },
+            |r| {
+                seen.push(r);
+                Ok(())
+            },
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(items.iter().map(|i| i * 10).collect::<Vec<_>>(), seen);
+    }
+
+    #[test]
+    fn output_error_stops_the_work() {
+        let items: Vec<usize> = (0..1000).collect();
+        let done = AtomicUsize::new(0);
+
+        let result = for_each_in_order(
+            &items,
+            2,
+            |&i| {
+                done.fetch_add(1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(1));
+                i
+            },
+            |i| if i == 3 { Err("failed") } else { Ok(()) },
+        );
+
+        assert_eq!(Err("failed"), result);
+        assert!(done.load(Ordering::SeqCst) < items.len());
+    }
+}
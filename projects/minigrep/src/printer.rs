@@ -0,0 +1,662 @@
+use std::io::{self, Write};
+
+use serde_json::json;
+
+use crate::{replace_spans, Config, Entry, Match, Sink};
+
+// What to print for each file
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OutputMode {
+    // the matching lines, with their context, like plain grep
+    Lines,
+    // only the number of matching lines, like grep -c
+    Count,
+    // only the name of each file that has a match, like grep -l
+    FilesWithMatches,
+    // one JSON object per line for each file, match and context line, plus a
+    // summary at the end, in the same shape as ripgrep --json
+    Json,
+}
+
+// When to highlight the matches, like grep --color
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ColorChoice {
+    // only when writing to a terminal
+    Auto,
+    Always,
+    Never,
+}
+impl ColorChoice {
+    pub fn use_color(&self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+// What to do with files that look binary, i.e. have a NUL byte near the start
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinaryMode {
+    // leave them out, so a directory search does not print garbage
+    Skip,
+    // search them, but only print "Binary file X matches", like grep
+    Report,
+    // search them like any other file, like grep -a
+    Text,
+}
+
+// bold red, the same as grep uses for matches
+const MATCH_COLOR: &str = "\x1b[1;31m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+// All output goes through here, so run only has to feed it the search results.
+// Writing to any Write lets the tests check the output without a terminal.
+pub struct Printer<W: Write> {
+    out: W,
+    mode: OutputMode,
+    show_path: bool,
+    line_number: bool,
+    byte_offset: bool,
+    with_context: bool,
+    color: bool,
+    // only kept when there is more than one, to tell which one matched
+    patterns: Vec<String>,
+    replace: Option<String>,
+    // print a diff of each replaced line instead of just the new line
+    preview: bool,
+    // the line number of the last line in the preview, to see where a new group starts
+    last_line: usize,
+    // the file being searched
+    name: String,
+    matches: usize,
+    first_entry: bool,
+    printed_before: bool,
+    // the file looked binary, so its lines are not printed
+    binary: bool,
+    // the first write that failed while being a Sink, see take_error
+    error: Option<io::Error>,
+    // totals over all files, for the JSON summary
+    files: usize,
+    files_with_matches: usize,
+    total_matches: usize,
+}
+impl<W: Write> Printer<W> {
+    // `color` is the resolved --color choice, since only the caller knows where `out` goes
+    pub fn new(out: W, config: &Config, show_path: bool, color: bool) -> Printer<W> {
+        Printer {
+            out,
+            mode: config.mode,
+            show_path,
+            line_number: config.line_number,
+            byte_offset: config.byte_offset,
+            with_context: config.before_context > 0 || config.after_context > 0,
+            color,
+            patterns: match config.patterns.len() {
+                1 => Vec::new(),
+                _ => config.patterns.clone(),
+            },
+            replace: config.replace.clone(),
+            preview: config.replace.is_some() && config.preview,
+            last_line: 0,
+            name: String::new(),
+            matches: 0,
+            first_entry: true,
+            printed_before: false,
+            binary: false,
+            error: None,
+            files: 0,
+            files_with_matches: 0,
+            total_matches: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    // Copy the output of another printer that searched one file on its own,
+    // separating it from what we printed before like print_entry does
+    pub fn append(&mut self, other: Printer<Vec<u8>>) -> io::Result<()> {
+        self.files += other.files;
+        self.files_with_matches += other.files_with_matches;
+        self.total_matches += other.total_matches;
+
+        let output = other.into_inner();
+        if output.is_empty() {
+            return Ok(());
+        }
+        if self.mode == OutputMode::Lines
+            && self.with_context
+            && self.printed_before
+            && !self.preview
+        {
+            writeln!(self.out, "--")?;
+        }
+        self.printed_before = true;
+        self.out.write_all(&output)
+    }
+
+    pub fn begin(&mut self, name: &str, binary: bool) -> io::Result<()> {
+        self.name = name.to_string();
+        self.binary = binary;
+        self.matches = 0;
+        self.first_entry = true;
+        self.last_line = 0;
+        if self.mode == OutputMode::Json {
+            let begin = json!({"type": "begin", "data": {"path": {"text": self.name}}});
+            writeln!(self.out, "{}", begin)?;
+        }
+        Ok(())
+    }
+
+    // A Sink cannot fail, so when writing fails the search is stopped and the
+    // error kept here until whoever started the search asks for it
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    // false when the search should stop
+    fn keep_error(&mut self, result: io::Result<bool>) -> bool {
+        result.unwrap_or_else(|e| {
+            self.error = Some(e);
+            false
+        })
+    }
+
+    // Returns false when the rest of the file does not matter, e.g. for -l
+    fn entry(&mut self, entry: Entry) -> io::Result<bool> {
+        if let Entry::Match(_) = entry {
+            self.matches += 1;
+        }
+        match self.mode {
+            // one match is all we need to know
+            OutputMode::Lines if self.binary => match entry {
+                Entry::Match(_) => {
+                    writeln!(self.out, "Binary file {} matches", self.name)?;
+                    self.first_entry = false;
+                    Ok(false)
+                }
+                _ => Ok(true),
+            },
+            OutputMode::Lines => {
+                self.print_entry(entry)?;
+                Ok(true)
+            }
+            OutputMode::Count => Ok(true),
+            OutputMode::FilesWithMatches => Ok(self.matches == 0),
+            OutputMode::Json => {
+                let (kind, result) = match entry {
+                    Entry::Match(result) => ("match", result),
+                    Entry::Context(result) => ("context", result),
+                    Entry::Break => return Ok(true),
+                };
+                writeln!(self.out, "{}", self.json_line(kind, &result))?;
+                Ok(true)
+            }
+        }
+    }
+
+    fn finish_file(&mut self) -> io::Result<()> {
+        self.files += 1;
+        self.total_matches += self.matches;
+        if self.matches > 0 {
+            self.files_with_matches += 1;
+        }
+
+        match self.mode {
+            OutputMode::Lines => self.printed_before |= !self.first_entry,
+            OutputMode::Count if self.show_path => {
+                writeln!(self.out, "{}:{}", self.name, self.matches)?
+            }
+            OutputMode::Count => writeln!(self.out, "{}", self.matches)?,
+            OutputMode::FilesWithMatches if self.matches > 0 => {
+                writeln!(self.out, "{}", self.name)?
+            }
+            OutputMode::FilesWithMatches => {}
+            OutputMode::Json => {
+                let end = json!({
+                    "type": "end",
+                    "data": {"path": {"text": self.name}, "stats": {"matches": self.matches}},
+                });
+                writeln!(self.out, "{}", end)?
+            }
+        }
+        Ok(())
+    }
+
+    // Whether any file had a match so far, or with -v any line that did not match
+    pub fn matched(&self) -> bool {
+        self.files_with_matches > 0
+    }
+
+    // Called once after the last file
+    pub fn summary(&mut self) -> io::Result<()> {
+        if self.mode == OutputMode::Json {
+            let summary = json!({
+                "type": "summary",
+                "data": {"stats": {
+                    "searches": self.files,
+                    "searches_with_match": self.files_with_matches,
+                    "matches": self.total_matches,
+                }},
+            });
+            writeln!(self.out, "{}", summary)?;
+        }
+        Ok(())
+    }
+
+    // Something like a unified diff, so the replacements can be checked before
+    // running again with --in-place
+    fn print_preview(&mut self, entry: Entry) -> io::Result<()> {
+        if self.first_entry {
+            writeln!(self.out, "--- {}\n+++ {}", self.name, self.name)?;
+            self.first_entry = false;
+        }
+        let (result, matched) = match entry {
+            Entry::Match(result) => (result, true),
+            Entry::Context(result) => (result, false),
+            Entry::Break => return Ok(()),
+        };
+        if self.last_line == 0 || result.line_number != self.last_line + 1 {
+            writeln!(self.out, "@@ line {} @@", result.line_number)?;
+        }
+        self.last_line = *result.line_range().end();
+
+        // with multiline, all the lines of the match and then what they become
+        if let (true, Some(with)) = (matched, &self.replace) {
+            for line in result.split_lines() {
+                writeln!(self.out, "-{}", line.line)?;
+            }
+            let replaced = replace_spans(&result.line, &result.spans, with);
+            for line in replaced.split('\n') {
+                writeln!(self.out, "+{}", line.strip_suffix('\r').unwrap_or(line))?;
+            }
+            return Ok(());
+        }
+        writeln!(self.out, " {}", result.line)
+    }
+
+    fn json_line(&self, kind: &str, result: &Match) -> serde_json::Value {
+        let submatches: Vec<_> = result
+            .spans
+            .iter()
+            .map(|s| {
+                json!({
+                    "match": {"text": &result.line[s.start..s.end]},
+                    "start": s.start,
+                    "end": s.end,
+                    "pattern": s.pattern,
+                })
+            })
+            .collect();
+        json!({
+            "type": kind,
+            "data": {
+                "path": {"text": self.name},
+                "lines": {"text": result.line},
+                "line_number": result.line_number,
+                "absolute_offset": result.byte_offset,
+                "submatches": submatches,
+            },
+        })
+    }
+
+    fn print_entry(&mut self, entry: Entry) -> io::Result<()> {
+        if self.preview {
+            return self.print_preview(entry);
+        }
+
+        // grep also separates the groups of different files
+        if self.first_entry && self.with_context && self.printed_before {
+            writeln!(self.out, "--")?;
+        }
+        self.first_entry = false;
+
+        // like grep, matches use : after the path and numbers, context lines use -
+        let (result, separator) = match entry {
+            Entry::Match(result) => (result, ':'),
+            Entry::Context(result) => (result, '-'),
+            Entry::Break => return writeln!(self.out, "--"),
+        };
+
+        if let Some(with) = &self.replace {
+            let replaced = replace_spans(&result.line, &result.spans, with);
+            self.print_prefix(&result, separator)?;
+            return writeln!(self.out, "{}", replaced);
+        }
+        // a multiline match is printed a line at a time, each with its own number
+        if result.line.contains('\n') {
+            for line in result.split_lines() {
+                self.print_line(&line, separator)?;
+            }
+            return Ok(());
+        }
+        self.print_line(&result, separator)
+    }
+
+    fn print_line(&mut self, result: &Match, separator: char) -> io::Result<()> {
+        self.print_prefix(result, separator)?;
+        if !self.color || result.spans.is_empty() {
+            // exactly the line, so plain output is the same as it always was
+            return writeln!(self.out, "{}", result.line);
+        }
+
+        let mut last = 0;
+        for span in result.spans.iter().filter(|s| s.start < s.end) {
+            let (start, end) = (span.start, span.end);
+            write!(
+                self.out,
+                "{}{}{}{}",
+                &result.line[last..start],
+                MATCH_COLOR,
+                &result.line[start..end],
+                RESET_COLOR
+            )?;
+            last = end;
+        }
+        writeln!(self.out, "{}", &result.line[last..])
+    }
+
+    fn print_prefix(&mut self, result: &Match, separator: char) -> io::Result<()> {
+        if self.show_path {
+            write!(self.out, "{}{}", self.name, separator)?;
+        }
+        if self.line_number {
+            write!(self.out, "{}{}", result.line_number, separator)?;
+        }
+        if self.byte_offset {
+            write!(self.out, "{}{}", result.byte_offset, separator)?;
+        }
+        if !self.patterns.is_empty() && !result.spans.is_empty() {
+            // each pattern that matched, once, in the order they matched in
+            let mut matched: Vec<&str> = Vec::new();
+            for span in &result.spans {
+                let pattern = self.patterns[span.pattern].as_str();
+                if !matched.contains(&pattern) {
+                    matched.push(pattern);
+                }
+            }
+            write!(self.out, "{}{}", matched.join(","), separator)?;
+        }
+        Ok(())
+    }
+}
+
+// The printer is the Sink for a search on the command line, after begin has
+// told it which file it is
+impl<W: Write> Sink for Printer<W> {
+    fn matched(&mut self, result: Match) -> bool {
+        let result = self.entry(Entry::Match(result));
+        self.keep_error(result)
+    }
+
+    fn context(&mut self, line: Match) -> bool {
+        let result = self.entry(Entry::Context(line));
+        self.keep_error(result)
+    }
+
+    fn context_break(&mut self) -> bool {
+        let result = self.entry(Entry::Break);
+        self.keep_error(result)
+    }
+
+    fn finish(&mut self) {
+        if self.error.is_none() {
+            let result = self.finish_file().map(|_| true);
+            self.keep_error(result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::config;
+    use crate::{QueryMatcher, Searcher};
+
+    // run the printer over a few in-memory files and return what it wrote
+    fn print(config: &Config, files: &[(&str, &str)]) -> String {
+        let matcher = QueryMatcher::new(config).unwrap();
+        let color = config.color.use_color(false);
+        let mut printer = Printer::new(Vec::new(), config, files.len() > 1, color);
+        for (name, contents) in files {
+            // the same test as walk::is_binary
+            printer.begin(name, contents.contains('\0')).unwrap();
+            Searcher::with_config(&matcher, config).search_str(contents, &mut printer);
+            assert!(printer.take_error().is_none());
+        }
+        printer.summary().unwrap();
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    const POEM: &str =
+        "I'm nobody! Who are you?\nAre you nobody, too?\nThen there's a pair of us - don't tell!";
+
+    #[test]
+    fn lines() {
+        let output = print(&config(&["-n", "nobody"]), &[("poem.txt", POEM)]);
+        assert_eq!(
+            "1:I'm nobody! Who are you?\n2:Are you nobody, too?\n",
+            output
+        );
+    }
+
+    #[test]
+    fn binary() {
+        let files = [("a.out", "\0ELF nobody\0\nnobody\n"), ("poem.txt", POEM)];
+        let output = print(&config(&["-n", "nobody"]), &files);
+        assert_eq!(
+            "Binary file a.out matches\npoem.txt:1:I'm nobody! Who are you?\npoem.txt:2:Are you nobody, too?\n",
+            output
+        );
+        // only the lines are left out
+        let output = print(&config(&["-c", "nobody"]), &files);
+        assert_eq!("a.out:2\npoem.txt:2\n", output);
+    }
+
+    #[test]
+    fn multiline() {
+        let output = print(
+            &config(&["-U", "-n", "--regex", r"nobody!\s+Who"]),
+            &[("poem.txt", POEM)],
+        );
+        assert_eq!("1:I'm nobody! Who are you?\n", output);
+        let output = print(
+            &config(&["-U", "-n", "--regex", r"you\?\nAre"]),
+            &[("poem.txt", POEM)],
+        );
+        assert_eq!(
+            "1:I'm nobody! Who are you?\n2:Are you nobody, too?\n",
+            output
+        );
+    }
+
+    #[test]
+    fn inverted() {
+        let output = print(&config(&["-v", "nobody"]), &[("poem.txt", POEM)]);
+        assert_eq!("Then there's a pair of us - don't tell!\n", output);
+    }
+
+    #[test]
+    fn count() {
+        let output = print(
+            &config(&["-c", "nobody"]),
+            &[("poem.txt", POEM), ("empty.txt", "")],
+        );
+        assert_eq!("poem.txt:2\nempty.txt:0\n", output);
+    }
+
+    #[test]
+    fn inverted_count() {
+        let output = print(&config(&["-vc", "nobody"]), &[("poem.txt", POEM)]);
+        assert_eq!("1\n", output);
+    }
+
+    #[test]
+    fn files_with_matches() {
+        let output = print(
+            &config(&["-l", "pair"]),
+            &[("poem.txt", POEM), ("other.txt", "nothing here")],
+        );
+        assert_eq!("poem.txt\n", output);
+    }
+
+    #[test]
+    fn context_between_files() {
+        let output = print(
+            &config(&["-A1", "you"]),
+            &[("a.txt", POEM), ("b.txt", "you\nand me")],
+        );
+        assert_eq!(
+            "a.txt:I'm nobody! Who are you?\n\
+             a.txt:Are you nobody, too?\n\
+             a.txt-Then there's a pair of us - don't tell!\n\
+             --\n\
+             b.txt:you\n\
+             b.txt-and me\n",
+            output
+        );
+    }
+
+    #[test]
+    fn color() {
+        let output = print(&config(&["--color=always", "o"]), &[("a.txt", "foo\nbar")]);
+        assert_eq!("f\x1b[1;31mo\x1b[0m\x1b[1;31mo\x1b[0m\n", output);
+    }
+
+    #[test]
+    fn no_color_is_plain() {
+        let output = print(&config(&["--color=never", "o"]), &[("a.txt", "foo\nbar")]);
+        assert_eq!("foo\n", output);
+        // auto is off when not writing to a terminal
+        let output = print(&config(&["o"]), &[("a.txt", "foo\nbar")]);
+        assert_eq!("foo\n", output);
+    }
+
+    #[test]
+    fn context_lines_are_not_colored() {
+        let output = print(
+            &config(&["--color=always", "-A1", "bar"]),
+            &[("a.txt", "bar\nfoo")],
+        );
+        assert_eq!("\x1b[1;31mbar\x1b[0m\nfoo\n", output);
+    }
+
+    #[test]
+    fn output_of_other_printers() {
+        let config = config(&["-A1", "you"]);
+        let mut printer = Printer::new(Vec::new(), &config, true, false);
+        for output in ["a.txt:you\n", "", "c.txt:you\nc.txt-and me\n"] {
+            let mut other = Printer::new(Vec::new(), &config, true, false);
+            other.out.extend(output.as_bytes());
+            printer.append(other).unwrap();
+        }
+        assert_eq!(
+            "a.txt:you\n--\nc.txt:you\nc.txt-and me\n",
+            String::from_utf8(printer.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn which_pattern_matched() {
+        let output = print(
+            &config(&["-n", "-e", "E42", "-e", "E7", "-e", "unused"]),
+            &[("log.txt", "ok\nE7 then E42 and E7\nE42")],
+        );
+        assert_eq!("2:E7,E42:E7 then E42 and E7\n3:E42:E42\n", output);
+    }
+
+    #[test]
+    fn json() {
+        let output = print(
+            &config(&["--json", "-B1", "o"]),
+            &[("a:b.txt", "Rust\nfoo"), ("empty.txt", "")],
+        );
+        let records: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(
+            vec![
+                json!({"type": "begin", "data": {"path": {"text": "a:b.txt"}}}),
+                json!({"type": "context", "data": {
+                    "path": {"text": "a:b.txt"},
+                    "lines": {"text": "Rust"},
+                    "line_number": 1,
+                    "absolute_offset": 0,
+                    "submatches": [],
+                }}),
+                json!({"type": "match", "data": {
+                    "path": {"text": "a:b.txt"},
+                    "lines": {"text": "foo"},
+                    "line_number": 2,
+                    "absolute_offset": 5,
+                    "submatches": [
+                        {"match": {"text": "o"}, "start": 1, "end": 2, "pattern": 0},
+                        {"match": {"text": "o"}, "start": 2, "end": 3, "pattern": 0},
+                    ],
+                }}),
+                json!({"type": "end", "data": {"path": {"text": "a:b.txt"}, "stats": {"matches": 1}}}),
+                json!({"type": "begin", "data": {"path": {"text": "empty.txt"}}}),
+                json!({"type": "end", "data": {"path": {"text": "empty.txt"}, "stats": {"matches": 0}}}),
+                json!({"type": "summary", "data": {"stats": {
+                    "searches": 2,
+                    "searches_with_match": 1,
+                    "matches": 1,
+                }}}),
+            ],
+            records
+        );
+    }
+
+    #[test]
+    fn replace_without_preview() {
+        let output = print(
+            &config(&["-n", "--replace", "somebody", "--no-preview", "nobody"]),
+            &[("poem.txt", POEM)],
+        );
+        assert_eq!(
+            "1:I'm somebody! Who are you?\n2:Are you somebody, too?\n",
+            output
+        );
+    }
+
+    #[test]
+    fn replace_preview() {
+        let output = print(
+            &config(&["-C1", "--replace", "they", "you"]),
+            &[("poem.txt", POEM), ("b.txt", "1\n2\n3\nyou\n5\n6\nyou")],
+        );
+        assert_eq!(
+            "--- poem.txt\n+++ poem.txt\n\
+             @@ line 1 @@\n\
+             -I'm nobody! Who are you?\n\
+             +I'm nobody! Who are they?\n\
+             -Are you nobody, too?\n\
+             +Are they nobody, too?\n\
+             \x20Then there's a pair of us - don't tell!\n\
+             --- b.txt\n+++ b.txt\n\
+             @@ line 3 @@\n\
+             \x203\n\
+             -you\n\
+             +they\n\
+             \x205\n\
+             \x206\n\
+             -you\n\
+             +they\n",
+            output
+        );
+    }
+
+    #[test]
+    fn replace_preview_groups_without_context() {
+        let output = print(&config(&["-r", "X", "x"]), &[("a.txt", "x\nx\n-\nx")]);
+        assert_eq!(
+            "--- a.txt\n+++ a.txt\n@@ line 1 @@\n-x\n+X\n-x\n+X\n@@ line 4 @@\n-x\n+X\n",
+            output
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{Match, Sink, Span};
+
+// The line with every matched span swapped for `with`. The spans come straight
+// from the matcher, so exactly what was reported as a match gets replaced.
+pub fn replace_spans(line: &str, spans: &[Span], with: &str) -> String {
+    let mut replaced = String::with_capacity(line.len());
+    let mut last = 0;
+    for span in spans {
+        replaced.push_str(&line[last..span.start]);
+        replaced.push_str(with);
+        last = span.end;
+    }
+    replaced.push_str(&line[last..]);
+    replaced
+}
+
+// Passes everything on to another sink, and keeps the spans of every match by
+// line number on the side. --in-place then replaces exactly what the search
+// found, and the preview showed, without matching the file a second time.
+//
+// The search goes on when the sink has seen enough, e.g. with -l, since every
+// match is still to be replaced.
+pub struct Recorder<'s, S: Sink + ?Sized> {
+    sink: &'s mut S,
+    forward: bool,
+    pub spans: BTreeMap<usize, Vec<Span>>,
+}
+impl<'s, S: Sink + ?Sized> Recorder<'s, S> {
+    pub fn new(sink: &'s mut S) -> Recorder<'s, S> {
+        Recorder {
+            sink,
+            forward: true,
+            spans: BTreeMap::new(),
+        }
+    }
+}
+impl<S: Sink + ?Sized> Sink for Recorder<'_, S> {
+    fn matched(&mut self, result: Match) -> bool {
+        self.spans.insert(result.line_number, result.spans.clone());
+        self.forward = self.forward && self.sink.matched(result);
+        true
+    }
+
+    fn context(&mut self, line: Match) -> bool {
+        self.forward = self.forward && self.sink.context(line);
+        true
+    }
+
+    fn context_break(&mut self) -> bool {
+        self.forward = self.forward && self.sink.context_break();
+        true
+    }
+
+    fn finish(&mut self) {
+        self.sink.finish();
+    }
+}
+
+// Rewrite the file with the spans of each line (see Recorder) replaced,
+// returning the number of lines changed.
+//
+// The new contents go to a temporary file next to the original, which is then
+// renamed over it, so a crash halfway leaves either the old or the new file,
+// never half of each. Line endings are kept byte for byte, and so are lines
+// that are not valid UTF-8, which the search skips for --in-place.
+pub fn replace_in_file(
+    path: &Path,
+    spans: &BTreeMap<usize, Vec<Span>>,
+    with: &str,
+) -> io::Result<usize> {
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a file that can be replaced",
+        )
+    })?;
+    let mut reader = BufReader::new(File::open(path)?);
+    let (temp_path, temp) = create_temp(path, file_name)?;
+    let mut writer = BufWriter::new(temp);
+
+    let result = copy_replacing(&mut reader, &mut writer, spans, with).and_then(|changed| {
+        let temp = writer.into_inner().map_err(|e| e.into_error())?;
+        temp.set_permissions(fs::metadata(path)?.permissions())?;
+        temp.sync_all()?;
+        Ok(changed)
+    });
+
+    match result {
+        Ok(0) => fs::remove_file(&temp_path).map(|_| 0),
+        Ok(changed) => fs::rename(&temp_path, path).map(|_| changed),
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+// for temporary files that no other run, or other thread of this one, uses
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+// A new file next to `path`, e.g. .a.txt.1234.0.minigrep-tmp. One left over
+// from a run that was killed is skipped rather than overwritten.
+fn create_temp(path: &Path, file_name: &OsStr) -> io::Result<(PathBuf, File)> {
+    loop {
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(
+            ".{}.{}.minigrep-tmp",
+            process::id(),
+            TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp_path = path.with_file_name(temp_name);
+        match File::options()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(temp) => return Ok((temp_path, temp)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn copy_replacing<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    spans: &BTreeMap<usize, Vec<Span>>,
+    with: &str,
+) -> io::Result<usize> {
+    let mut buf = Vec::new();
+    let mut changed = 0;
+    for line_number in 1.. {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let line = buf.strip_suffix(b"\n").unwrap_or(&buf);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let ending = &buf[line.len()..];
+
+        // empty matches have nothing to replace
+        let spans: Vec<Span> = spans
+            .get(&line_number)
+            .into_iter()
+            .flatten()
+            .filter(|s| s.start < s.end)
+            .copied()
+            .collect();
+        match std::str::from_utf8(line) {
+            Ok(text) if !spans.is_empty() => {
+                writer.write_all(replace_spans(text, &spans, with).as_bytes())?;
+                writer.write_all(ending)?;
+                changed += 1;
+            }
+            _ => writer.write_all(&buf)?,
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{config, TempDir};
+    use crate::{search_file, Entry, Printer, QueryMatcher, Searcher};
+
+    fn span(start: usize, end: usize) -> Span {
+        Span {
+            start,
+            end,
+            pattern: 0,
+        }
+    }
+
+    #[test]
+    fn replaces_each_span() {
+        assert_eq!(
+            "I'm somebody! Who are you, somebody?",
+            replace_spans(
+                "I'm nobody! Who are you, nobody?",
+                &[span(4, 10), span(25, 31)],
+                "somebody"
+            )
+        );
+        assert_eq!("unchanged", replace_spans("unchanged", &[], "x"));
+    }
+
+    #[test]
+    fn replaces_in_place() {
+        let dir = TempDir::new("replace");
+        let path = dir.join("roads.txt");
+        // "Straße", then a Latin-1 é that is not valid UTF-8
+        fs::write(&path, b"Stra\xc3\x9fe\r\nlat\xe9 straSSe\nnothing\nSTRASSE").unwrap();
+
+        // the spans come from the search, which skips the line it could not
+        // write back
+        let matcher = QueryMatcher::case_insensitive("strasse");
+        let mut searcher = Searcher::new(&matcher);
+        searcher.skip_invalid_utf8 = true;
+        let mut results: Vec<Entry> = Vec::new();
+        let mut recorder = Recorder::new(&mut results);
+        searcher.search_slice(&fs::read(&path).unwrap(), &mut recorder);
+        let spans = recorder.spans;
+        assert_eq!(vec![&1, &4], spans.keys().collect::<Vec<_>>());
+
+        assert_eq!(2, replace_in_file(&path, &spans, "road").unwrap());
+        // the line with invalid UTF-8 and the line endings are left alone
+        assert_eq!(
+            b"road\r\nlat\xe9 straSSe\nnothing\nroad".to_vec(),
+            fs::read(&path).unwrap()
+        );
+        // nothing left over next to it
+        assert_eq!(1, fs::read_dir(&*dir).unwrap().count());
+
+        // nor in the way, when a killed run left its temporary files behind
+        let next = TEMP_FILES.load(Ordering::Relaxed);
+        for n in next..next + 3 {
+            let stale = dir.join(format!(".roads.txt.{}.{}.minigrep-tmp", process::id(), n));
+            fs::write(stale, "stale").unwrap();
+        }
+        fs::write(&path, "Strasse\n").unwrap();
+        let mut spans = BTreeMap::new();
+        spans.insert(1, vec![span(0, 7)]);
+        assert_eq!(1, replace_in_file(&path, &spans, "road").unwrap());
+        assert_eq!("road\n", fs::read_to_string(&path).unwrap());
+
+        // nothing to replace, nothing changes
+        assert_eq!(0, replace_in_file(&path, &BTreeMap::new(), "road").unwrap());
+    }
+
+    #[test]
+    fn preview_shows_what_is_written() {
+        let dir = TempDir::new("preview");
+        let path = dir.join("menu.txt");
+        fs::write(&path, b"caf\xe9 foo\nfoo bar\n").unwrap();
+        let file = path.to_str().unwrap();
+        let config = config(&["-r", "X", "--in-place", "foo", file]);
+        let matcher = QueryMatcher::new(&config).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, false, false);
+        search_file(&path, &matcher, &config, &mut printer).unwrap();
+
+        assert_eq!(
+            format!("--- {0}\n+++ {0}\n@@ line 2 @@\n-foo bar\n+X bar\n", file),
+            String::from_utf8(printer.into_inner()).unwrap()
+        );
+        assert_eq!(b"caf\xe9 foo\nX bar\n".to_vec(), fs::read(&path).unwrap());
+    }
+}
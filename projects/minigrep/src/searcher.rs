@@ -0,0 +1,464 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+use crate::{Config, Entry, Match, Matcher, Span};
+
+// Gets the results of a search as they are found. The printer is one, a
+// Vec<Entry> that collects them is another.
+//
+// Each callback returns false to stop searching the rest of the input, e.g.
+// when one match is all that matters. They cannot fail; a sink that can, like
+// the printer, keeps its error for whoever started the search.
+pub trait Sink {
+    // a matching line, or with invert one that does not match
+    fn matched(&mut self, result: Match) -> bool;
+
+    // a line before or after a match, its spans are always empty
+    fn context(&mut self, _line: Match) -> bool {
+        true
+    }
+
+    // the gap between two groups of lines that are not next to each other
+    fn context_break(&mut self) -> bool {
+        true
+    }
+
+    // the whole input has been searched, or the sink asked to stop
+    fn finish(&mut self) {}
+}
+
+// everything in the order it was found, e.g. to check the results in a test
+impl Sink for Vec<Entry> {
+    fn matched(&mut self, result: Match) -> bool {
+        self.push(Entry::Match(result));
+        true
+    }
+
+    fn context(&mut self, line: Match) -> bool {
+        self.push(Entry::Context(line));
+        true
+    }
+
+    fn context_break(&mut self) -> bool {
+        self.push(Entry::Break);
+        true
+    }
+}
+
+// Searches any input one line at a time with a Matcher and reports the
+// results to a Sink
+pub struct Searcher<'m> {
+    matcher: &'m dyn Matcher,
+    // report the lines that do not match instead, like grep -v
+    pub invert: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    // match the whole input at once, so a match can span lines (-U)
+    pub multiline: bool,
+    // Lines that are not valid UTF-8 are searched with the bad bytes replaced
+    // by U+FFFD. This never reports them instead, for --in-place, which could
+    // not write such a line back the way it was shown.
+    pub skip_invalid_utf8: bool,
+}
+impl<'m> Searcher<'m> {
+    // just the matching lines
+    pub fn new(matcher: &'m dyn Matcher) -> Searcher<'m> {
+        Searcher {
+            matcher,
+            invert: false,
+            before_context: 0,
+            after_context: 0,
+            multiline: false,
+            skip_invalid_utf8: false,
+        }
+    }
+
+    // the way the command line asked for
+    pub fn with_config(matcher: &'m dyn Matcher, config: &Config) -> Searcher<'m> {
+        Searcher {
+            matcher,
+            invert: config.invert,
+            before_context: config.before_context,
+            after_context: config.after_context,
+            multiline: config.multiline,
+            skip_invalid_utf8: config.in_place,
+        }
+    }
+
+    pub fn search_str<S: Sink + ?Sized>(&self, contents: &str, sink: &mut S) {
+        self.search_slice(contents.as_bytes(), sink);
+    }
+
+    // Only the current line and the before context lines are ever in memory,
+    // however big the input is. Except with multiline, which needs all of it.
+    pub fn search_reader<R, S>(&self, mut reader: R, sink: &mut S) -> io::Result<()>
+    where
+        R: BufRead,
+        S: Sink + ?Sized,
+    {
+        if self.multiline {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            self.search_slice(&bytes, sink);
+            return Ok(());
+        }
+
+        let mut lines = Lines::new(self);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            if reader.read_until(b'\n', &mut buf)? == 0 || !lines.next(self, &buf, sink) {
+                break;
+            }
+        }
+        sink.finish();
+        Ok(())
+    }
+
+    // The whole input is already in memory, e.g. a memory-mapped file, so the
+    // lines are searched where they are instead of being copied out first
+    pub fn search_slice<S: Sink + ?Sized>(&self, bytes: &[u8], sink: &mut S) {
+        if self.multiline {
+            return self.search_multiline(bytes, sink);
+        }
+
+        let mut lines = Lines::new(self);
+        let mut start = 0;
+        while start < bytes.len() {
+            let end = line_end(bytes, start);
+            if !lines.next(self, &bytes[start..end], sink) {
+                break;
+            }
+            start = end;
+        }
+        sink.finish();
+    }
+
+    // The matches are found in the whole text, and each one is reported with
+    // all of the lines it touches as one Match, see Match::line_range. Matches
+    // that share a line are reported together.
+    fn search_multiline<S: Sink + ?Sized>(&self, bytes: &[u8], sink: &mut S) {
+        let text = String::from_utf8_lossy(bytes);
+        let mut lines = Lines::new(self);
+        // the lines outside of the blocks are still looked at one by one, for
+        // the context and for -v
+        let single = |lines: &mut Lines, sink: &mut S, start: usize, is_match: bool| {
+            let end = line_end(text.as_bytes(), start);
+            let line = Cow::Borrowed(without_line_ending(&text[start..end]));
+            (
+                end,
+                lines.add(end - start, line, Vec::new(), is_match, 1, sink),
+            )
+        };
+
+        let mut start = 0;
+        for block in blocks(&text, self.matcher.find_all(&text)) {
+            while start < block.start {
+                match single(&mut lines, sink, start, self.invert) {
+                    (end, true) => start = end,
+                    (_, false) => return sink.finish(),
+                }
+            }
+            if self.invert {
+                while start < block.end {
+                    match single(&mut lines, sink, start, false) {
+                        (end, true) => start = end,
+                        (_, false) => return sink.finish(),
+                    }
+                }
+                continue;
+            }
+
+            let line = without_line_ending(&text[block.start..block.end]);
+            let spans = block
+                .spans
+                .into_iter()
+                .map(|span| Span {
+                    // a match may end with the line ending we just dropped
+                    start: span.start.min(line.len()),
+                    end: span.end.min(line.len()),
+                    ..span
+                })
+                .collect();
+            let line_count = text[block.start..block.end].lines().count();
+            let len = block.end - block.start;
+            if !lines.add(len, Cow::Borrowed(line), spans, true, line_count, sink) {
+                return sink.finish();
+            }
+            start = block.end;
+        }
+        while start < text.len() {
+            match single(&mut lines, sink, start, self.invert) {
+                (end, true) => start = end,
+                (_, false) => break,
+            }
+        }
+        sink.finish();
+    }
+}
+
+// The lines a match touches, from the start of its first line to the end of
+// its last, with the spans relative to the start
+struct Block {
+    start: usize,
+    end: usize,
+    spans: Vec<Span>,
+}
+
+// The matches grouped by the lines they are on, in order
+fn blocks(text: &str, spans: Vec<Span>) -> Vec<Block> {
+    let bytes = text.as_bytes();
+    let mut blocks: Vec<Block> = Vec::new();
+    for span in spans {
+        let start = memchr::memrchr(b'\n', &bytes[..span.start]).map_or(0, |i| i + 1);
+        // an empty match after the last line ending is not on any line
+        if start == text.len() {
+            continue;
+        }
+        // the line of the last byte in the match, which may be its line ending
+        let end = line_end(bytes, span.end.saturating_sub(1).max(start));
+        match blocks.last_mut() {
+            Some(last) if start < last.end => {
+                last.end = last.end.max(end);
+                last.spans.push(Span {
+                    start: span.start - last.start,
+                    end: span.end - last.start,
+                    ..span
+                });
+            }
+            _ => blocks.push(Block {
+                start,
+                end,
+                spans: vec![Span {
+                    start: span.start - start,
+                    end: span.end - start,
+                    ..span
+                }],
+            }),
+        }
+    }
+    blocks
+}
+
+// just past the line ending of the line that `start` is on
+fn line_end(bytes: &[u8], start: usize) -> usize {
+    match memchr::memchr(b'\n', &bytes[start..]) {
+        Some(i) => start + i + 1,
+        None => bytes.len(),
+    }
+}
+
+// like str::lines, drop the line ending
+fn without_line_ending(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+// Where a search is up to, fed one line (or with multiline one block of lines)
+// at a time.
+//
+// Like grep -B/-A: keep the last `before` lines around in case the next line
+// matches, and count down the `after` lines following a match. Groups that
+// overlap or touch are merged, otherwise they are separated by a break.
+struct Lines {
+    before: usize,
+    after: usize,
+    line_number: usize,
+    byte_offset: usize,
+    before_lines: VecDeque<Match>,
+    after_left: usize,
+    last_printed: Option<usize>,
+}
+impl Lines {
+    fn new(searcher: &Searcher) -> Lines {
+        Lines {
+            before: searcher.before_context,
+            after: searcher.after_context,
+            line_number: 0,
+            byte_offset: 0,
+            before_lines: VecDeque::with_capacity(searcher.before_context),
+            after_left: 0,
+            last_printed: None,
+        }
+    }
+
+    // `buf` is the line with its line ending, returns false to stop
+    fn next<S: Sink + ?Sized>(&mut self, searcher: &Searcher, buf: &[u8], sink: &mut S) -> bool {
+        // like str::lines, drop the line ending
+        let raw = buf.strip_suffix(b"\n").unwrap_or(buf);
+        let raw = raw.strip_suffix(b"\r").unwrap_or(raw);
+        // a stray invalid byte should not stop us from searching the rest of the line
+        let line = String::from_utf8_lossy(raw);
+
+        let skip = searcher.skip_invalid_utf8 && matches!(line, Cow::Owned(_));
+
+        // whether it matches first, most lines do not and that is all they need;
+        // a line can also match with nothing to highlight, e.g. with --json-field
+        let matched = !skip && searcher.matcher.is_match(&line);
+        let spans = match matched && !searcher.invert {
+            true => searcher.matcher.find_all(&line),
+            false => Vec::new(),
+        };
+        let is_match = match searcher.invert {
+            _ if skip => false,
+            true => !matched,
+            false => matched,
+        };
+        self.add(buf.len(), line, spans, is_match, 1, sink)
+    }
+
+    // `len` is how many bytes of the input the line took, with its line ending
+    fn add<S: Sink + ?Sized>(
+        &mut self,
+        len: usize,
+        line: Cow<str>,
+        spans: Vec<Span>,
+        is_match: bool,
+        line_count: usize,
+        sink: &mut S,
+    ) -> bool {
+        let (before, after) = (self.before, self.after);
+        let line_number = self.line_number + 1;
+        self.line_number += line_count;
+        let offset = self.byte_offset;
+        self.byte_offset += len;
+
+        // only copy the line if we are going to keep it
+        if !is_match && self.after_left == 0 && before == 0 {
+            return true;
+        }
+        let result = Match {
+            line_number,
+            byte_offset: offset,
+            line: line.into_owned(),
+            spans,
+        };
+
+        if is_match {
+            let first = self.before_lines.front().unwrap_or(&result).line_number;
+            if let Some(last) = self.last_printed {
+                if (before > 0 || after > 0) && first > last + 1 && !sink.context_break() {
+                    return false;
+                }
+            }
+            if !self
+                .before_lines
+                .drain(..)
+                .all(|context| sink.context(context))
+            {
+                return false;
+            }
+            self.last_printed = Some(self.line_number);
+            self.after_left = after;
+            sink.matched(result)
+        } else if self.after_left > 0 {
+            self.last_printed = Some(self.line_number);
+            self.after_left -= 1;
+            sink.context(result)
+        } else {
+            if self.before_lines.len() == before {
+                self.before_lines.pop_front();
+            }
+            self.before_lines.push_back(result);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a Matcher of our own, not a query from the command line
+    struct Digits;
+    impl Matcher for Digits {
+        fn find_all(&self, line: &str) -> Vec<Span> {
+            line.char_indices()
+                .filter(|(_, c)| c.is_ascii_digit())
+                .map(|(i, _)| Span {
+                    start: i,
+                    end: i + 1,
+                    pattern: 0,
+                })
+                .collect()
+        }
+    }
+
+    // stops at the first match, like -l
+    #[derive(Default)]
+    struct First {
+        line: Option<usize>,
+        finished: bool,
+    }
+    impl Sink for First {
+        fn matched(&mut self, result: Match) -> bool {
+            self.line = Some(result.line_number);
+            false
+        }
+
+        fn finish(&mut self) {
+            self.finished = true;
+        }
+    }
+
+    const LOG: &str = "started\nretry 1\nretry 2\ndone\n";
+
+    #[test]
+    fn custom_matcher() {
+        let mut results = Vec::new();
+        Searcher::new(&Digits).search_str(LOG, &mut results);
+        let lines: Vec<_> = results
+            .into_iter()
+            .map(|entry| match entry {
+                Entry::Match(result) => result.line,
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(vec!["retry 1", "retry 2"], lines);
+    }
+
+    #[test]
+    fn sink_stops_the_search() {
+        let mut first = First::default();
+        Searcher::new(&Digits).search_str(LOG, &mut first);
+        assert_eq!(Some(2), first.line);
+        assert!(first.finished);
+    }
+
+    #[test]
+    fn context_and_invert() {
+        let mut searcher = Searcher::new(&Digits);
+        searcher.invert = true;
+        searcher.after_context = 1;
+        let mut results = Vec::new();
+        searcher.search_str(LOG, &mut results);
+        assert_eq!(4, results.len());
+        assert!(matches!(&results[1], Entry::Context(c) if c.line == "retry 1"));
+        assert_eq!(Entry::Break, results[2]);
+    }
+
+    #[test]
+    fn multiline_match_spans_lines() {
+        let matcher = crate::QueryMatcher::Regex(
+            regex::RegexBuilder::new(r"#\[test\]\s*fn \w+")
+                .build()
+                .unwrap(),
+        );
+        let mut searcher = Searcher::new(&matcher);
+        searcher.multiline = true;
+        searcher.after_context = 1;
+        let mut results = Vec::new();
+        searcher.search_str("mod tests {\n#[test]\nfn foo() {}\n}\n", &mut results);
+        assert_eq!(2, results.len());
+        match &results[0] {
+            Entry::Match(result) => {
+                assert_eq!(2..=3, result.line_range());
+                assert_eq!("#[test]\nfn foo() {}", result.line);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        // the line numbers carry on after the block
+        assert!(matches!(&results[1], Entry::Context(c) if c.line_number == 4));
+    }
+}
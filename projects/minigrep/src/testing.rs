@@ -0,0 +1,50 @@
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::Config;
+
+// What the tests of several modules share
+
+// the config for the arguments after the program name
+pub fn config(args: &[&str]) -> Config {
+    let args = ["minigrep"].iter().chain(args).map(|s| s.to_string());
+    Config::new(args).unwrap()
+}
+
+// A directory of its own under the system temp dir, removed again when it is
+// dropped, so a failed assertion does not leave it behind
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    // `name` keeps the tests that run at the same time apart
+    pub fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("minigrep-{}-{}", name, process::id()));
+        // left over from a run that was killed
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,252 @@
+use std::error;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ignore::overrides::OverrideBuilder;
+use ignore::types::TypesBuilder;
+use ignore::WalkBuilder;
+
+use crate::{Config, Error};
+
+// Expand the paths given on the command line into the list of files to search.
+// Directories are walked recursively, in sorted order so the output is stable.
+//
+// Like ripgrep, the walk skips hidden files and whatever .gitignore and .ignore
+// files say (also outside of a git repository), unless --hidden or --no-ignore
+// are given. Paths named on the command line are always searched.
+//
+// A path that is missing or a directory that cannot be read is handed to
+// `report`, and the walk goes on with the rest unless that returns the error.
+pub fn files<F>(config: &Config, report: F) -> Result<Vec<PathBuf>, Error>
+where
+    F: FnMut(Error) -> Result<(), Error>,
+{
+    files_in(&config.paths, config, report)
+}
+
+// The same for other paths than the ones to search, e.g. a directory to index
+pub fn files_in<F>(paths: &[String], config: &Config, mut report: F) -> Result<Vec<PathBuf>, Error>
+where
+    F: FnMut(Error) -> Result<(), Error>,
+{
+    let mut files = Vec::new();
+
+    // --glob and --iglob are relative to where we are
+    let mut overrides = OverrideBuilder::new(".");
+    for glob in &config.globs {
+        overrides.add(glob).map_err(usage)?;
+    }
+    overrides.case_insensitive(true).map_err(usage)?;
+    for glob in &config.iglobs {
+        overrides.add(glob).map_err(usage)?;
+    }
+
+    let mut types = TypesBuilder::new();
+    types.add_defaults();
+    for name in &config.types {
+        types.select(name);
+    }
+    for name in &config.types_not {
+        types.negate(name);
+    }
+
+    // a bad glob or an unknown type is a mistake in the arguments
+    let overrides = overrides.build().map_err(usage)?;
+    let types = types.build().map_err(usage)?;
+
+    for path in paths {
+        let path = Path::new(path);
+        // - is standard input, not a file on disk
+        if path == Path::new("-") {
+            files.push(path.to_path_buf());
+            continue;
+        }
+        let walk = WalkBuilder::new(path)
+            .standard_filters(!config.no_ignore)
+            .hidden(!config.hidden)
+            .require_git(false)
+            .overrides(overrides.clone())
+            .types(types.clone())
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+        for entry in walk {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    report(walk_error(path, e))?;
+                    continue;
+                }
+            };
+            if entry.file_type().is_some_and(|t| t.is_file()) {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn usage(e: ignore::Error) -> Error {
+    Error::Usage(e.to_string())
+}
+
+// An error while walking, e.g. a directory we may not read, blamed on the
+// file it was about when the walk knows it
+fn walk_error(root: &Path, e: ignore::Error) -> Error {
+    let path = error_path(&e).unwrap_or(root).to_path_buf();
+    // a symlink loop, say, is not an OS error
+    let source = os_error(&e).unwrap_or_else(|| io::Error::other(e.to_string()));
+    Error::io(path, source)
+}
+
+// The errors from the walk say which path they are about in their message,
+// so dig out only what the OS said
+fn os_error(e: &ignore::Error) -> Option<io::Error> {
+    let mut source: &(dyn error::Error + 'static) = e.io_error()?;
+    loop {
+        if let Some(code) = source
+            .downcast_ref::<io::Error>()
+            .and_then(|e| e.raw_os_error())
+        {
+            return Some(io::Error::from_raw_os_error(code));
+        }
+        source = source.source()?;
+    }
+}
+
+fn error_path(e: &ignore::Error) -> Option<&Path> {
+    match e {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::WithDepth { err, .. } => error_path(err),
+        _ => None,
+    }
+}
+
+// Same heuristic as grep: a NUL byte near the start means it is not a text file.
+// Only the start, so a memory-mapped file is not read all the way through here.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(8 * 1024)].contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::testing::{config, TempDir};
+
+    // a small tree to walk, in its own directory under the system temp dir
+    fn tree(name: &str) -> TempDir {
+        let root = TempDir::new(&format!("walk-{}", name));
+        for dir in ["src", "target/debug", ".hidden"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "src/lib.rs",
+            "src/README.md",
+            "target/debug/build.rs",
+            ".hidden/secret.rs",
+            "notes.TXT",
+            "skipped.log",
+        ] {
+            fs::write(root.join(file), "query\n").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.join(".ignore"), "*.log\n").unwrap();
+        root
+    }
+
+    fn found(root: &Path, args: &[&str]) -> Vec<String> {
+        let mut args = [&["query"], args].concat();
+        args.push(root.to_str().unwrap());
+        files(&config(&args), Err)
+            .unwrap()
+            .iter()
+            .map(|p| p.strip_prefix(root).unwrap().to_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn binary() {
+        assert!(is_binary(b"ELF\0\0\x01"));
+        assert!(!is_binary(b"Rust:\nsafe, fast, productive."));
+    }
+
+    #[test]
+    fn honors_ignore_files_and_hidden_files() {
+        let root = tree("ignore");
+        assert_eq!(
+            vec!["notes.TXT", "src/README.md", "src/lib.rs"],
+            found(&root, &[])
+        );
+        assert_eq!(
+            vec![
+                ".gitignore",
+                ".hidden/secret.rs",
+                ".ignore",
+                "notes.TXT",
+                "skipped.log",
+                "src/README.md",
+                "src/lib.rs",
+                "target/debug/build.rs",
+            ],
+            found(&root, &["--hidden", "--no-ignore"])
+        );
+    }
+
+    #[test]
+    fn globs_and_types() {
+        let root = tree("globs");
+        assert_eq!(vec!["src/lib.rs"], found(&root, &["--glob", "*.rs"]));
+        assert_eq!(
+            vec!["notes.TXT", "src/lib.rs"],
+            found(&root, &["-g", "!*.md"])
+        );
+        assert!(found(&root, &["--glob", "*.txt"]).is_empty());
+        assert_eq!(vec!["notes.TXT"], found(&root, &["--iglob", "*.txt"]));
+        assert_eq!(vec!["src/lib.rs"], found(&root, &["--type", "rust"]));
+        assert_eq!(
+            vec!["notes.TXT", "src/lib.rs"],
+            found(&root, &["-T", "markdown"])
+        );
+    }
+
+    #[test]
+    fn named_files_are_always_searched() {
+        let root = tree("named");
+        let log = root.join("skipped.log");
+        let config = config(&["query", "-t", "rust", log.to_str().unwrap()]);
+        assert_eq!(vec![log.clone()], files(&config, Err).unwrap());
+    }
+
+    #[test]
+    fn unknown_type() {
+        let err = files(&config(&["query", "-t", "nosuchtype", "src"]), Err).unwrap_err();
+        assert!(matches!(err, Error::Usage(_)));
+    }
+
+    #[test]
+    fn missing_path() {
+        let root = tree("missing");
+        let src = root.join("src");
+        let mut errors = Vec::new();
+        let args = config(&["query", "no/such/dir", src.to_str().unwrap()]);
+        let files = files(&args, |e| {
+            errors.push(e);
+            Ok(())
+        })
+        .unwrap();
+        // the rest is still walked
+        assert_eq!(vec![src.join("README.md"), src.join("lib.rs")], files);
+        match &errors[..] {
+            [Error::Io {
+                path: Some(path),
+                source,
+            }] => {
+                assert_eq!(Path::new("no/such/dir"), path);
+                assert_eq!(io::ErrorKind::NotFound, source.kind());
+            }
+            _ => panic!("expected an error about no/such/dir"),
+        }
+        assert!(files_in(&[String::from("no/such/dir")], &args, Err).is_err());
+    }
+}